@@ -0,0 +1,424 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use chrono::{DateTime, NaiveDate, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::config::profile;
+use crate::dates::DateRange;
+use crate::models::{GithubSearchResponseItem, TrelloCard};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS github_items (
+    id INTEGER NOT NULL,
+    category TEXT NOT NULL,
+    org TEXT NOT NULL,
+    created_on TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    data TEXT NOT NULL,
+    PRIMARY KEY (id, category)
+);
+CREATE TABLE IF NOT EXISTS trello_cards (
+    id TEXT PRIMARY KEY,
+    board_id TEXT NOT NULL,
+    board_name TEXT NOT NULL,
+    date_last_activity TEXT NOT NULL,
+    done_at TEXT,
+    data TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS sync_state (
+    key TEXT PRIMARY KEY,
+    synced_at TEXT NOT NULL,
+    covered_from TEXT,
+    covered_to TEXT,
+    full_synced_at TEXT
+);
+CREATE TABLE IF NOT EXISTS meta (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+";
+
+/// How far before the last sync to look for updates, in case of clock skew.
+const SYNC_OVERLAP_MINUTES: i64 = 10;
+/// How often to fetch a whole search again rather than only what was updated. Searching for
+/// updates never finds items that stopped matching, e.g. issues unassigned from the user.
+const FULL_SYNC_DAYS: i64 = 7;
+
+/// What to fetch from GitHub to bring the cache up to date for one search.
+pub struct GithubSync {
+    /// The range of creation dates to search.
+    pub date_range: DateRange,
+    /// Only fetch items updated since then. `None` means fetch everything in the range.
+    pub updated_since: Option<DateTime<Utc>>,
+}
+
+pub struct CachedTrelloCard {
+    pub board_name: String,
+    pub card: TrelloCard,
+    pub done_at: Option<DateTime<Utc>>,
+}
+
+/// On-disk store of fetched GitHub and Trello data, so reports only need to fetch
+/// what changed since the last run and can be generated offline.
+/// It lives in the user's data directory, e.g. `~/.local/share/self-assessment/cache.sqlite3`.
+pub struct Cache {
+    conn: Connection,
+    path: PathBuf,
+    created: bool,
+}
+
+impl Cache {
+    pub fn open() -> anyhow::Result<Self> {
+        let path = Self::path()?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Couldn't create {}", dir.display()))?;
+        }
+        let created = !path.exists();
+        let conn = Connection::open(&path)
+            .with_context(|| format!("Couldn't open the cache at {}", path.display()))?;
+        conn.execute_batch(SCHEMA)
+            .context("Couldn't set up the cache")?;
+        Ok(Cache {
+            conn,
+            path,
+            created,
+        })
+    }
+
+    /// Where the cache of the selected profile lives.
+    pub fn path() -> anyhow::Result<PathBuf> {
+        Self::path_for(profile())
+    }
+
+    /// Where this cache lives.
+    pub fn location(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether opening the cache created it, so it has nothing in it yet.
+    pub fn created(&self) -> bool {
+        self.created
+    }
+
+    /// Location of the cache of a profile. Profiles may belong to different accounts,
+    /// so each one has its own cache.
+    pub fn path_for(profile: Option<&str>) -> anyhow::Result<PathBuf> {
+        let data_dir = dirs::data_dir().context("Couldn't find the user data directory")?;
+        let file_name = match profile {
+            Some(profile) => format!("cache-{}.sqlite3", profile),
+            None => "cache.sqlite3".to_string(),
+        };
+        Ok(data_dir.join("self-assessment").join(file_name))
+    }
+
+    /// Work out how to sync a search. If the cache already covers the requested date range,
+    /// only items updated since the last sync are fetched, across the whole covered range,
+    /// unless the last full sync is more than [`FULL_SYNC_DAYS`] old.
+    pub fn github_sync(&self, key: &str, requested: &DateRange) -> anyhow::Result<GithubSync> {
+        let state = self
+            .conn
+            .query_row(
+                "SELECT synced_at, covered_from, covered_to, full_synced_at
+                 FROM sync_state WHERE key = ?1",
+                params![key],
+                |row| {
+                    Ok((
+                        row.get::<_, DateTime<Utc>>(0)?,
+                        row.get::<_, Option<NaiveDate>>(1)?,
+                        row.get::<_, Option<NaiveDate>>(2)?,
+                        row.get::<_, Option<DateTime<Utc>>>(3)?,
+                    ))
+                },
+            )
+            .optional()?;
+
+        Ok(match state {
+            Some((synced_at, covered_from, covered_to, full_synced_at))
+                if covered_from.is_none_or(|c| requested.from.is_some_and(|r| r >= c))
+                    && covered_to.is_none_or(|c| requested.to.is_some_and(|r| r <= c)) =>
+            {
+                let full_sync_due = full_synced_at
+                    .is_none_or(|at| Utc::now() - at > chrono::Duration::days(FULL_SYNC_DAYS));
+                GithubSync {
+                    date_range: DateRange {
+                        from: covered_from,
+                        to: covered_to,
+                    },
+                    updated_since: match full_sync_due {
+                        true => None,
+                        false => Some(synced_at - chrono::Duration::minutes(SYNC_OVERLAP_MINUTES)),
+                    },
+                }
+            }
+            _ => GithubSync {
+                date_range: *requested,
+                updated_since: None,
+            },
+        })
+    }
+
+    /// Record that a search covering `date_range` was fully synced as of `synced_at`.
+    /// `full` is whether every item was fetched, rather than only those updated since the last sync.
+    pub fn finish_github_sync(
+        &self,
+        key: &str,
+        date_range: &DateRange,
+        synced_at: DateTime<Utc>,
+        full: bool,
+    ) -> anyhow::Result<()> {
+        self.conn.execute(
+            "INSERT INTO sync_state (key, synced_at, covered_from, covered_to, full_synced_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT (key) DO UPDATE SET
+                 synced_at = excluded.synced_at,
+                 covered_from = excluded.covered_from,
+                 covered_to = excluded.covered_to,
+                 full_synced_at = COALESCE(excluded.full_synced_at, sync_state.full_synced_at)",
+            params![
+                key,
+                synced_at,
+                date_range.from,
+                date_range.to,
+                full.then_some(synced_at)
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn store_github_items(
+        &self,
+        category: &str,
+        org: &str,
+        items: &[GithubSearchResponseItem],
+    ) -> anyhow::Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        {
+            // The REST and GraphQL APIs give PRs different ids,
+            // so drop any copy of the same PR fetched with the other one
+            let mut delete_other_copy = tx.prepare(
+                "DELETE FROM github_items
+                 WHERE category = ?1 AND id != ?2 AND json_extract(data, '$.html_url') = ?3",
+            )?;
+            let mut insert = tx.prepare(
+                "INSERT OR REPLACE INTO github_items (id, category, org, created_on, updated_at, data)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )?;
+            for item in items {
+                delete_other_copy.execute(params![category, item.id, item.html_url])?;
+                insert.execute(params![
+                    item.id,
+                    category,
+                    org.to_lowercase(),
+                    item.created_at.date_naive(),
+                    item.updated_at,
+                    serde_json::to_string(item)?,
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Forget the cached items of a category in an organisation created within `date_range`,
+    /// other than `kept`. After fetching a whole search, the others no longer match it.
+    pub fn remove_other_github_items(
+        &self,
+        category: &str,
+        org: &str,
+        date_range: &DateRange,
+        kept: &[GithubSearchResponseItem],
+    ) -> anyhow::Result<()> {
+        let kept: HashSet<u64> = kept.iter().map(|item| item.id).collect();
+        let tx = self.conn.unchecked_transaction()?;
+        {
+            let mut query = tx.prepare(
+                "SELECT id FROM github_items
+                 WHERE category = ?1 AND org = ?2
+                   AND (?3 IS NULL OR created_on >= ?3)
+                   AND (?4 IS NULL OR created_on <= ?4)",
+            )?;
+            let ids = query
+                .query_map(
+                    params![category, org.to_lowercase(), date_range.from, date_range.to],
+                    |row| row.get::<_, u64>(0),
+                )?
+                .collect::<Result<Vec<u64>, _>>()?;
+
+            let mut delete =
+                tx.prepare("DELETE FROM github_items WHERE id = ?1 AND category = ?2")?;
+            for id in ids.into_iter().filter(|id| !kept.contains(id)) {
+                delete.execute(params![id, category])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Forget every cached GitHub item and search, e.g. because they came from another GitHub host
+    /// or account.
+    pub fn clear_github(&self) -> anyhow::Result<()> {
+        self.conn.execute_batch(
+            "DELETE FROM github_items;
+             DELETE FROM sync_state WHERE key LIKE 'github:%';",
+        )?;
+        Ok(())
+    }
+
+    /// Replace the cached data of an item, e.g. once more details about it have been fetched.
+    pub fn update_github_item(
+        &self,
+        category: &str,
+        item: &GithubSearchResponseItem,
+    ) -> anyhow::Result<()> {
+        self.conn.execute(
+            "UPDATE github_items SET data = ?3 WHERE id = ?1 AND category = ?2",
+            params![item.id, category, serde_json::to_string(item)?],
+        )?;
+        Ok(())
+    }
+
+    /// Cached items of a category in an organisation, created within `date_range`, newest first.
+    pub fn github_items(
+        &self,
+        category: &str,
+        org: &str,
+        date_range: &DateRange,
+    ) -> anyhow::Result<Vec<GithubSearchResponseItem>> {
+        let mut query = self.conn.prepare(
+            "SELECT data FROM github_items
+             WHERE category = ?1 AND org = ?2
+               AND (?3 IS NULL OR created_on >= ?3)
+               AND (?4 IS NULL OR created_on <= ?4)",
+        )?;
+        let rows = query.query_map(
+            params![category, org.to_lowercase(), date_range.from, date_range.to],
+            |row| row.get::<_, String>(0),
+        )?;
+
+        let mut items = rows
+            .map(|data| Ok(serde_json::from_str(&data?)?))
+            .collect::<anyhow::Result<Vec<GithubSearchResponseItem>>>()?;
+        items.sort_by_key(|i| std::cmp::Reverse(i.created_at));
+        Ok(items)
+    }
+
+    /// Replace the cached cards of a board with the ones just fetched.
+    /// Rows are only rewritten for cards that changed since they were cached.
+    pub fn store_trello_board(
+        &self,
+        board_id: &str,
+        board_name: &str,
+        cards: &[TrelloCard],
+        done_dates: &HashMap<String, DateTime<Utc>>,
+    ) -> anyhow::Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        {
+            let card_ids = cards.iter().map(|c| c.id.as_str()).collect::<Vec<&str>>();
+            let mut existing = tx.prepare("SELECT id FROM trello_cards WHERE board_id = ?1")?;
+            let stale = existing
+                .query_map(params![board_id], |row| row.get::<_, String>(0))?
+                .collect::<Result<Vec<String>, _>>()?
+                .into_iter()
+                .filter(|id| !card_ids.contains(&id.as_str()));
+            for id in stale {
+                tx.execute("DELETE FROM trello_cards WHERE id = ?1", params![id])?;
+            }
+
+            let mut upsert = tx.prepare(
+                "INSERT INTO trello_cards (id, board_id, board_name, date_last_activity, done_at, data)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                 ON CONFLICT (id) DO UPDATE SET
+                    board_id = excluded.board_id,
+                    board_name = excluded.board_name,
+                    date_last_activity = excluded.date_last_activity,
+                    done_at = COALESCE(excluded.done_at, trello_cards.done_at),
+                    data = excluded.data
+                 WHERE excluded.date_last_activity > trello_cards.date_last_activity
+                    OR excluded.board_name != trello_cards.board_name
+                    OR excluded.done_at IS NOT NULL",
+            )?;
+            for card in cards {
+                upsert.execute(params![
+                    card.id,
+                    board_id,
+                    board_name,
+                    card.date_last_activity,
+                    done_dates.get(&card.id),
+                    serde_json::to_string(card)?,
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Forget the cards of boards the user is no longer a member of.
+    pub fn retain_trello_boards(&self, board_ids: &[&String]) -> anyhow::Result<()> {
+        let mut query = self
+            .conn
+            .prepare("SELECT DISTINCT board_id FROM trello_cards")?;
+        let cached_boards = query
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        for board_id in cached_boards {
+            if !board_ids.contains(&&board_id) {
+                self.conn.execute(
+                    "DELETE FROM trello_cards WHERE board_id = ?1",
+                    params![board_id],
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Every cached card, with its board name and the date it was moved to a "Done" list, if known.
+    pub fn trello_cards(&self) -> anyhow::Result<Vec<CachedTrelloCard>> {
+        let mut query = self
+            .conn
+            .prepare("SELECT board_name, data, done_at FROM trello_cards")?;
+        let rows = query.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, Option<DateTime<Utc>>>(2)?,
+            ))
+        })?;
+
+        rows.map(|row| {
+            let (board_name, data, done_at) = row?;
+            Ok(CachedTrelloCard {
+                board_name,
+                card: serde_json::from_str(&data)?,
+                done_at,
+            })
+        })
+        .collect()
+    }
+
+    pub fn set_meta<T: Serialize>(&self, key: &str, value: &T) -> anyhow::Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO meta (key, value) VALUES (?1, ?2)",
+            params![key, serde_json::to_string(value)?],
+        )?;
+        Ok(())
+    }
+
+    pub fn meta<T: DeserializeOwned>(&self, key: &str) -> anyhow::Result<Option<T>> {
+        let value = self
+            .conn
+            .query_row(
+                "SELECT value FROM meta WHERE key = ?1",
+                params![key],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()?;
+        Ok(match value {
+            Some(value) => Some(serde_json::from_str(&value)?),
+            None => None,
+        })
+    }
+}
@@ -0,0 +1,251 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+use chrono::{Datelike, Days, NaiveDate};
+use handlebars::html_escape;
+
+use crate::models::{
+    GithubSearchResponseItem, RepoSummary, ReportData, Summary, TemplateCharts, TrelloCard,
+};
+use crate::summary::{reviewed_at, week_of};
+
+const AUTHORED_COLOUR: &str = "#052962";
+const REVIEWED_COLOUR: &str = "#1a7f37";
+const TRELLO_COLOUR: &str = "#0079bf";
+const TEXT_COLOUR: &str = "#57606a";
+const GRID_COLOUR: &str = "#d0d7de";
+
+/// Bar charts only show this many of the largest bars.
+const MAX_BARS: usize = 15;
+
+const WIDTH: f64 = 760.0;
+const LEGEND_HEIGHT: f64 = 24.0;
+/// Room left of horizontal bars for their labels.
+const LABEL_WIDTH: f64 = 220.0;
+/// Room right of horizontal bars for their totals.
+const TOTAL_WIDTH: f64 = 40.0;
+const BAR_HEIGHT: f64 = 18.0;
+const BAR_GAP: f64 = 8.0;
+/// Room left of the timeline for the axis labels.
+const AXIS_WIDTH: f64 = 30.0;
+const TIMELINE_HEIGHT: f64 = 160.0;
+/// Room each month label of the timeline needs, so they don't overlap.
+const MONTH_LABEL_WIDTH: f64 = 64.0;
+
+/// Draw the charts of an HTML report, as SVG to embed in the page.
+pub fn draw_charts(report: &ReportData, summary: &Summary) -> TemplateCharts {
+    TemplateCharts {
+        timeline: timeline_chart(&report.authored.items, &report.reviewed.items),
+        repos: repo_chart(&summary.repos),
+        trello_boards: report.trello_cards.as_ref().and_then(trello_chart),
+    }
+}
+
+/// Vertical bars of the PRs authored and reviewed each week, from the first week with a PR
+/// to the last.
+fn timeline_chart(
+    authored: &[GithubSearchResponseItem],
+    reviewed: &[GithubSearchResponseItem],
+) -> Option<String> {
+    let mut counts: BTreeMap<NaiveDate, [usize; 2]> = BTreeMap::new();
+    for pr in authored {
+        counts
+            .entry(week_of(pr.created_at.date_naive()))
+            .or_default()[0] += 1;
+    }
+    for pr in reviewed {
+        counts
+            .entry(week_of(reviewed_at(pr).date_naive()))
+            .or_default()[1] += 1;
+    }
+
+    let (&first, &last) = (counts.keys().next()?, counts.keys().next_back()?);
+    let weeks: Vec<(NaiveDate, [usize; 2])> =
+        std::iter::successors(Some(first), |week| week.checked_add_days(Days::new(7)))
+            .take_while(|week| *week <= last)
+            .map(|week| (week, counts.get(&week).copied().unwrap_or_default()))
+            .collect();
+    let max = weeks.iter().flat_map(|(_, c)| *c).max().unwrap_or(0).max(1);
+
+    let plot_top = LEGEND_HEIGHT + 8.0;
+    let plot_bottom = plot_top + TIMELINE_HEIGHT;
+    let slot = (WIDTH - AXIS_WIDTH) / weeks.len() as f64;
+    let bar_width = (slot * 0.4).min(12.0);
+
+    let mut svg = open_svg(plot_bottom + 24.0, "PRs authored and reviewed each week");
+    legend(
+        &mut svg,
+        AXIS_WIDTH,
+        &[("Authored", AUTHORED_COLOUR), ("Reviewed", REVIEWED_COLOUR)],
+    );
+    for (value, y) in [(0, plot_bottom), (max, plot_top)] {
+        let _ = write!(
+            svg,
+            r#"<line x1="{x}" y1="{y:.1}" x2="{WIDTH}" y2="{y:.1}" stroke="{GRID_COLOUR}"/><text x="{tx}" y="{ty:.1}" text-anchor="end" fill="{TEXT_COLOUR}">{value}</text>"#,
+            x = AXIS_WIDTH,
+            tx = AXIS_WIDTH - 6.0,
+            ty = y + 4.0,
+        );
+    }
+
+    // Months are labelled where they start, skipping any that would overlap the previous label
+    let mut next_label_x = AXIS_WIDTH;
+
+    for (i, (week, week_counts)) in weeks.iter().enumerate() {
+        let slot_x = AXIS_WIDTH + i as f64 * slot;
+        for (series, (&count, (name, colour))) in week_counts
+            .iter()
+            .zip([("authored", AUTHORED_COLOUR), ("reviewed", REVIEWED_COLOUR)])
+            .enumerate()
+        {
+            if count == 0 {
+                continue;
+            }
+            let height = count as f64 / max as f64 * TIMELINE_HEIGHT;
+            let _ = write!(
+                svg,
+                r#"<rect x="{x:.1}" y="{y:.1}" width="{bar_width:.1}" height="{height:.1}" fill="{colour}"><title>Week of {week}: {count} {name}</title></rect>"#,
+                x = slot_x + slot / 2.0 - bar_width + series as f64 * bar_width,
+                y = plot_bottom - height,
+            );
+        }
+
+        let new_month = i == 0 || weeks[i - 1].0.month() != week.month();
+        if new_month && slot_x >= next_label_x && slot_x + MONTH_LABEL_WIDTH <= WIDTH {
+            let _ = write!(
+                svg,
+                r#"<text x="{x:.1}" y="{y:.1}" fill="{TEXT_COLOUR}">{label}</text>"#,
+                x = slot_x,
+                y = plot_bottom + 16.0,
+                label = week.format("%b %Y"),
+            );
+            next_label_x = slot_x + MONTH_LABEL_WIDTH;
+        }
+    }
+
+    svg.push_str("</svg>");
+    Some(svg)
+}
+
+/// Horizontal bars of the PRs authored and reviewed in each repository.
+fn repo_chart(repos: &[RepoSummary]) -> Option<String> {
+    let mut rows: Vec<(&str, Vec<usize>)> = repos
+        .iter()
+        .filter(|r| r.authored + r.reviewed > 0)
+        .map(|r| (r.name.as_str(), vec![r.authored, r.reviewed]))
+        .collect();
+    rows.sort_by_key(|(_, counts)| std::cmp::Reverse(counts.iter().sum::<usize>()));
+    rows.truncate(MAX_BARS);
+
+    bar_chart(
+        "PRs authored and reviewed in each repository",
+        &rows,
+        &[("Authored", AUTHORED_COLOUR), ("Reviewed", REVIEWED_COLOUR)],
+    )
+}
+
+/// Horizontal bars of the Trello cards in each board.
+fn trello_chart(cards: &HashMap<String, Vec<TrelloCard>>) -> Option<String> {
+    let mut rows: Vec<(&str, Vec<usize>)> = cards
+        .iter()
+        .filter(|(_, cards)| !cards.is_empty())
+        .map(|(board, cards)| (board.as_str(), vec![cards.len()]))
+        .collect();
+    rows.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    rows.truncate(MAX_BARS);
+
+    bar_chart(
+        "Trello cards in each board",
+        &rows,
+        &[("Cards", TRELLO_COLOUR)],
+    )
+}
+
+/// Horizontal bars, one per row, stacking the values of each series, named and coloured by
+/// `series`. A legend is only drawn when there is more than one series.
+fn bar_chart(title: &str, rows: &[(&str, Vec<usize>)], series: &[(&str, &str)]) -> Option<String> {
+    if rows.is_empty() {
+        return None;
+    }
+    let max = rows
+        .iter()
+        .map(|(_, values)| values.iter().sum::<usize>())
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let top = if series.len() > 1 { LEGEND_HEIGHT } else { 0.0 };
+    let bar_area = WIDTH - LABEL_WIDTH - TOTAL_WIDTH;
+
+    let mut svg = open_svg(top + rows.len() as f64 * (BAR_HEIGHT + BAR_GAP), title);
+    if series.len() > 1 {
+        legend(&mut svg, LABEL_WIDTH, series);
+    }
+
+    for (i, (label, values)) in rows.iter().enumerate() {
+        let y = top + i as f64 * (BAR_HEIGHT + BAR_GAP);
+        let short = html_escape(&truncate(label, 32));
+        let label = html_escape(label);
+        let _ = write!(
+            svg,
+            r#"<text x="{x}" y="{ty:.1}" text-anchor="end" fill="{TEXT_COLOUR}">{short}<title>{label}</title></text>"#,
+            x = LABEL_WIDTH - 8.0,
+            ty = y + BAR_HEIGHT - 5.0,
+        );
+
+        let mut x = LABEL_WIDTH;
+        for (&value, (name, colour)) in values.iter().zip(series) {
+            if value == 0 {
+                continue;
+            }
+            let width = value as f64 / max as f64 * bar_area;
+            let _ = write!(
+                svg,
+                r#"<rect x="{x:.1}" y="{y:.1}" width="{width:.1}" height="{BAR_HEIGHT}" fill="{colour}"><title>{label}: {value}{name}</title></rect>"#,
+                // A single series is already named by the chart's title
+                name = match series.len() {
+                    1 => String::new(),
+                    _ => format!(" {}", name.to_lowercase()),
+                },
+            );
+            x += width;
+        }
+        let _ = write!(
+            svg,
+            r#"<text x="{tx:.1}" y="{ty:.1}" fill="{TEXT_COLOUR}">{total}</text>"#,
+            tx = x + 6.0,
+            ty = y + BAR_HEIGHT - 5.0,
+            total = values.iter().sum::<usize>(),
+        );
+    }
+
+    svg.push_str("</svg>");
+    Some(svg)
+}
+
+/// Start an SVG that scales down to fit narrow pages, described by `title` for screen readers.
+fn open_svg(height: f64, title: &str) -> String {
+    format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {WIDTH} {height:.1}" width="100%" style="max-width: {WIDTH}px" role="img" aria-label="{title}" font-family="sans-serif" font-size="12"><title>{title}</title>"#,
+        title = html_escape(title),
+    )
+}
+
+/// A row of coloured squares naming each series, along the top of a chart from `x`.
+fn legend(svg: &mut String, mut x: f64, series: &[(&str, &str)]) {
+    for (name, colour) in series {
+        let _ = write!(
+            svg,
+            r#"<rect x="{x}" y="4" width="12" height="12" fill="{colour}"/><text x="{tx}" y="14" fill="{TEXT_COLOUR}">{name}</text>"#,
+            tx = x + 18.0,
+        );
+        x += 100.0;
+    }
+}
+
+/// Shorten text to `max` characters, ending with an ellipsis if it was cut.
+fn truncate(text: &str, max: usize) -> String {
+    match text.char_indices().nth(max) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.to_string(),
+    }
+}
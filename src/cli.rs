@@ -1,21 +1,38 @@
-use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
+use clap::{ArgEnum, Parser, Subcommand};
+
+use crate::dates::parse_date;
 
 #[derive(Parser)]
 #[clap(author, version, about, long_about = None)]
 pub struct Args {
+    /// Use a named profile, with its own credentials and settings, instead of the default one.
+    /// Settings are read from the [Settings:<PROFILE>] section of ~/.selfassessment,
+    /// falling back to [Settings].
+    #[clap(long, global = true)]
+    pub profile: Option<String>,
     #[clap(subcommand)]
     pub command: Commands,
 }
 
 #[derive(Subcommand)]
 pub enum Commands {
-    /// Authenticate to Github.
+    /// Authenticate to Github. The token is checked with GitHub before it is saved.
     /// This is needed for the CLI tool to access
     /// the Guardian's private repositories to which the user has access.
     /// You can get a personal access token at <https://github.com/settings/tokens/new>
     Auth {
         /// Github authentication token.
-        token: String,
+        #[clap(required_unless_present = "token-stdin")]
+        token: Option<String>,
+        /// Read the token from standard input instead, so it doesn't end up in the shell history.
+        #[clap(long, conflicts_with = "token")]
+        token_stdin: bool,
+        /// Save the token without checking it with GitHub first.
+        #[clap(long)]
+        no_verify: bool,
     },
     /// Authenticate to Trello. An API key and a server token are required.
     /// For more information, run
@@ -31,30 +48,195 @@ pub enum Commands {
         /// You can get a server token by following the link at <https://trello.com/app-key>
         /// Both the API key and the server token need to be set for the generated report
         /// to include Trello cards.
-        token: String,
+        #[clap(required_unless_present = "token-stdin")]
+        token: Option<String>,
+        /// Read the server token from standard input instead, so it doesn't end up in the shell history.
+        #[clap(long, conflicts_with = "token")]
+        token_stdin: bool,
+        /// Save the credentials without checking them with Trello first.
+        #[clap(long)]
+        no_verify: bool,
+    },
+    /// Check the settings, credentials and cache, and explain how to fix any problems.
+    /// GitHub tokens are checked for the repo scope, single sign-on authorisation
+    /// for each organisation, and their expiry date.
+    Doctor,
+    /// Move credentials saved in plain text in ~/.selfassessment to the OS keyring.
+    /// Without a keyring, the file is made readable only by you instead.
+    MigrateCredentials,
+    /// Remove saved credentials, from the keyring and from ~/.selfassessment.
+    Logout {
+        /// Which credentials to remove.
+        #[clap(arg_enum, default_value = "all")]
+        service: LogoutService,
+        /// Also revoke the Trello server token, so it can't be used any more.
+        /// GitHub personal access tokens can only be revoked on GitHub.
+        #[clap(long)]
+        revoke: bool,
     },
-    /// Generate a report containing a list of PRs authored and reviewed by you,
-    /// as well as an optional report of Trello boards and cards you are assigned to.
+    /// List or delete profiles.
+    Profile {
+        #[clap(subcommand)]
+        command: ProfileCommands,
+    },
+    /// Generate a report containing a list of PRs authored and reviewed by you and the issues
+    /// you opened, commented on or were assigned, as well as an optional report of Trello boards and cards you are assigned to.
     /// For more information, run self-assessment generate-report --help
-    GenerateReport {
-        /// Match PRs and Trello cards that were created up until this date.
-        /// The date must be in the YYYY-MM-DD format.
-        #[clap(short, long, default_value = "*")]
-        from: String,
-        /// Match PRs and Trello cards that were created up until this date.
-        /// The date must be in the YYYY-MM-DD format.
-        #[clap(short, long, default_value = "*")]
-        to: String,
-        /// Skip Trello report.
-        /// Passing this flag generates a report that does not include Trello cards.
-        #[clap(short, long)]
-        skip_trello: bool,
+    GenerateReport(ReportOptions),
+}
+
+#[derive(Subcommand)]
+pub enum ProfileCommands {
+    /// List the profiles and what is set up in each.
+    List,
+    /// Delete a profile, along with its credentials, settings and cache.
+    Delete {
+        /// Name of the profile.
+        name: String,
     },
 }
 
-#[derive(Debug)]
+#[derive(clap::Args)]
+pub struct ReportOptions {
+    /// Match PRs and Trello cards that were created from this date onwards.
+    /// The date must be in the YYYY-MM-DD format.
+    #[clap(short, long, parse(try_from_str = parse_date))]
+    pub from: Option<NaiveDate>,
+    /// Match PRs and Trello cards that were created up until this date.
+    /// The date must be in the YYYY-MM-DD format.
+    #[clap(short, long, parse(try_from_str = parse_date))]
+    pub to: Option<NaiveDate>,
+    /// Match PRs and Trello cards in a named period instead of between --from and --to:
+    /// this-month, last-month, this-quarter, last-quarter, this-year, last-year,
+    /// a year (2025), a half (H1-2026), a quarter (Q3-2025),
+    /// or a review cycle defined in the [Cycles] section of ~/.selfassessment.
+    #[clap(long, conflicts_with_all = &["from", "to", "since"])]
+    pub period: Option<String>,
+    /// Match PRs and Trello cards created in the given time up to today instead of from --from,
+    /// e.g. 30d, 2w, 6m or 1y.
+    /// Without any date flags, the PERIOD or SINCE setting in the [Settings] section
+    /// of ~/.selfassessment is used, if set.
+    #[clap(long, conflicts_with = "from")]
+    pub since: Option<String>,
+    /// Skip Trello report.
+    /// Passing this flag generates a report that does not include Trello cards.
+    #[clap(short, long)]
+    pub skip_trello: bool,
+    /// Which Trello card date to match against --from and --to:
+    /// when the card was created, when it was moved into a "Done" list,
+    /// its due date, or when it was last active.
+    #[clap(long, arg_enum, default_value = "activity")]
+    pub trello_date: TrelloDateField,
+    /// GitHub organisation to search for PRs. Repeat the flag to search several organisations,
+    /// e.g. --org guardian --org guardian-labs.
+    /// Defaults to the comma-separated ORGS setting in the [Settings] section of ~/.selfassessment,
+    /// or to the guardian organisation if that isn't set either.
+    #[clap(long = "org", value_name = "ORG", multiple_occurrences = true)]
+    pub orgs: Vec<String>,
+    /// Format of the generated report.
+    /// Markdown is handy for pasting into documents and forms,
+    /// JSON exports the collected data for use in other tools,
+    /// and CSV writes one row per PR for spreadsheets (plus a second file for Trello cards).
+    /// Defaults to the FORMAT setting in the [Settings] section of ~/.selfassessment, or html.
+    #[clap(long, arg_enum)]
+    pub format: Option<ReportFormat>,
+    /// Where to write the report. Either a file path, or a directory in which to write
+    /// the report under its default name (e.g. 2024-05-13-self-assessment.html).
+    /// Defaults to the current directory.
+    #[clap(short, long)]
+    pub output: Option<PathBuf>,
+    /// Don't open the report once it has been generated.
+    #[clap(long)]
+    pub no_open: bool,
+    /// Handlebars template to render HTML and Markdown reports with, instead of the built-in one.
+    /// Other .hbs files in the same directory can be included as partials, e.g. {{> header}}.
+    /// Defaults to the TEMPLATE setting in the [Settings] section of ~/.selfassessment.
+    #[clap(long)]
+    pub template: Option<PathBuf>,
+    /// Read the GitHub token from standard input for this report only, e.g. in CI.
+    /// It takes precedence over the GITHUB_TOKEN environment variable and saved credentials.
+    #[clap(long, conflicts_with = "offline")]
+    pub token_stdin: bool,
+    /// Generate the report from data cached by previous runs, without going online.
+    #[clap(long)]
+    pub offline: bool,
+    /// Which GitHub API to fetch PRs and issues with. The GraphQL API fetches the details of PRs
+    /// along with the search results, so it needs far fewer requests than the REST API.
+    /// Defaults to the GITHUB_API setting in the [Settings] section of ~/.selfassessment, or rest.
+    #[clap(long, arg_enum)]
+    pub github_api: Option<GithubApi>,
+    /// Group the PRs and issues in the report by repository, the month or quarter they were opened,
+    /// or label. PRs and issues with several labels are listed under each of them.
+    /// Defaults to the GROUP_BY setting in the [Settings] section of ~/.selfassessment,
+    /// or no grouping.
+    #[clap(long, arg_enum)]
+    pub group_by: Option<GroupBy>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+pub enum TrelloDateField {
+    Created,
+    Done,
+    Due,
+    Activity,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+pub enum LogoutService {
+    Github,
+    Trello,
+    All,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum AuthType {
     GitHubAuthToken,
     TrelloApiKey,
     TrelloServerToken,
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+pub enum GithubApi {
+    Rest,
+    Graphql,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+pub enum GroupBy {
+    Repo,
+    Month,
+    Quarter,
+    Label,
+}
+
+impl GroupBy {
+    /// Name of the grouping, as passed to `--group-by`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            GroupBy::Repo => "repo",
+            GroupBy::Month => "month",
+            GroupBy::Quarter => "quarter",
+            GroupBy::Label => "label",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+pub enum ReportFormat {
+    Html,
+    Markdown,
+    Json,
+    Csv,
+}
+
+impl ReportFormat {
+    /// File extension of reports in this format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ReportFormat::Html => "html",
+            ReportFormat::Markdown => "md",
+            ReportFormat::Json => "json",
+            ReportFormat::Csv => "csv",
+        }
+    }
+}
@@ -0,0 +1,188 @@
+use std::fs::OpenOptions;
+use std::sync::OnceLock;
+
+use anyhow::{bail, Context};
+use ini::Ini;
+
+/// GitHub organisation searched when none is passed on the command line or set in the config file.
+pub const DEFAULT_GITHUB_ORG: &str = "guardian";
+
+/// Section listing the named profiles, so profiles whose credentials are all in the keyring are known.
+const PROFILES_SECTION: &str = "Profiles";
+
+/// Sections of `~/.selfassessment` that every profile has its own copy of.
+const PROFILE_SECTIONS: [&str; 3] = ["GitHub", "Trello", "Settings"];
+
+static PROFILE: OnceLock<Option<String>> = OnceLock::new();
+
+/// Select the profile used for the rest of the run. `None` is the default profile.
+pub fn set_profile(profile: Option<String>) -> anyhow::Result<()> {
+    if let Some(name) = &profile {
+        validate_profile_name(name)?;
+    }
+    PROFILE
+        .set(profile)
+        .map_err(|_| anyhow::anyhow!("The profile has already been selected"))
+}
+
+/// The profile selected with `--profile`, or `None` for the default profile.
+pub fn profile() -> Option<&'static str> {
+    PROFILE.get().and_then(|p| p.as_deref())
+}
+
+fn validate_profile_name(name: &str) -> anyhow::Result<()> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        bail!(
+            "\"{}\" is not a valid profile name. Use letters, digits, - and _ only.",
+            name
+        );
+    }
+    Ok(())
+}
+
+/// Name of a section or key for a profile, e.g. `GitHub` in the default profile
+/// and `GitHub:work` in the work profile.
+pub fn profile_name(name: &str, profile: Option<&str>) -> String {
+    match profile {
+        Some(profile) => format!("{}:{}", name, profile),
+        None => name.to_string(),
+    }
+}
+
+/// Remember that a named profile exists.
+pub fn register_profile(profile: &str) -> anyhow::Result<()> {
+    let mut store = load_store();
+    if store
+        .section(Some(PROFILES_SECTION))
+        .is_some_and(|s| s.contains_key(profile))
+    {
+        return Ok(());
+    }
+    store
+        .with_section(Some(PROFILES_SECTION))
+        .set(profile, chrono::Local::now().date_naive().to_string());
+    write_store(&store)
+}
+
+/// Every named profile, sorted by name.
+pub fn list_profiles() -> Vec<String> {
+    let store = load_store();
+    let mut profiles = store
+        .section(Some(PROFILES_SECTION))
+        .map(|s| {
+            s.iter()
+                .map(|(k, _)| k.to_string())
+                .collect::<Vec<String>>()
+        })
+        .unwrap_or_default();
+    for section in store.sections().flatten() {
+        if let Some((base, profile)) = section.split_once(':') {
+            if PROFILE_SECTIONS.contains(&base) {
+                profiles.push(profile.to_string());
+            }
+        }
+    }
+    profiles.sort();
+    profiles.dedup();
+    profiles
+}
+
+/// Remove a named profile's sections from `~/.selfassessment`.
+pub fn delete_profile_sections(profile: &str) -> anyhow::Result<()> {
+    let mut store = load_store();
+    for section in PROFILE_SECTIONS {
+        store.delete(Some(profile_name(section, Some(profile))));
+    }
+    store.delete_from(Some(PROFILES_SECTION), profile);
+    write_store(&store)
+}
+
+/// Location of the file holding credentials and settings.
+pub fn store_path() -> String {
+    shellexpand::tilde("~/.selfassessment").to_string()
+}
+
+/// Load the credential and settings store, or an empty one if it doesn't exist yet.
+pub fn load_store() -> Ini {
+    Ini::load_from_file(store_path()).unwrap_or_default()
+}
+
+/// Save the credential and settings store, readable only by the user since it may hold credentials.
+pub fn write_store(store: &Ini) -> anyhow::Result<()> {
+    let path = store_path();
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let mut file = options
+        .open(&path)
+        .with_context(|| format!("Couldn't write to {}", path))?;
+    // The mode only applies to new files, so tighten the permissions of existing ones too
+    #[cfg(unix)]
+    file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+    store
+        .write_to(&mut file)
+        .with_context(|| format!("Couldn't write to {}", path))?;
+    Ok(())
+}
+
+/// Load a setting from the `[Settings]` section of `~/.selfassessment`.
+/// In a named profile, settings from its `[Settings:<profile>]` section take precedence.
+pub fn get_setting(key: &str) -> Option<String> {
+    get_profile_setting(key, profile())
+}
+
+/// Load a setting of a profile.
+pub fn get_profile_setting(key: &str, profile: Option<&str>) -> Option<String> {
+    let store = load_store();
+    let setting = |section: String| {
+        store
+            .section(Some(section))
+            .and_then(|s| s.get(key))
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+    };
+    match profile {
+        Some(_) => setting(profile_name("Settings", profile)),
+        None => None,
+    }
+    .or_else(|| setting("Settings".to_string()))
+}
+
+/// Load a review cycle, e.g. `2026-01-01..2026-06-30`, from the `[Cycles]` section of `~/.selfassessment`.
+pub fn get_review_cycle(name: &str) -> Option<String> {
+    load_store()
+        .section(Some("Cycles"))
+        .and_then(|s| s.get(name))
+        .map(|v| v.trim().to_string())
+}
+
+/// Work out which GitHub organisations to search.
+/// Organisations passed with `--org` take precedence over the comma-separated `ORGS` setting,
+/// which in turn takes precedence over the Guardian organisation.
+pub fn github_orgs(cli_orgs: Vec<String>) -> Vec<String> {
+    let mut orgs = if !cli_orgs.is_empty() {
+        cli_orgs
+    } else {
+        match get_setting("ORGS") {
+            Some(orgs) => orgs
+                .split(',')
+                .map(|o| o.trim().to_string())
+                .filter(|o| !o.is_empty())
+                .collect(),
+            None => vec![],
+        }
+    };
+
+    if orgs.is_empty() {
+        orgs.push(DEFAULT_GITHUB_ORG.to_string());
+    }
+    orgs.sort();
+    orgs.dedup();
+    orgs
+}
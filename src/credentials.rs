@@ -1,49 +1,609 @@
-use ini::Ini;
+use std::fmt::Display;
+use std::io::{BufRead, IsTerminal};
+use std::sync::Once;
 
-use crate::cli::AuthType;
+use anyhow::{bail, Context};
+use keyring::Entry;
+
+use crate::cli::{AuthType, LogoutService};
+use crate::config::{
+    get_profile_setting, load_store, profile, profile_name, register_profile, store_path,
+    write_store,
+};
+use crate::github_host::{GithubHost, DEFAULT_GITHUB_URL};
+use crate::trello::revoke_trello_token;
+
+/// Service name credentials are saved under in the OS keyring.
+const KEYRING_SERVICE: &str = "self-assessment";
+
+impl AuthType {
+    pub const ALL: [AuthType; 3] = [
+        AuthType::GitHubAuthToken,
+        AuthType::TrelloApiKey,
+        AuthType::TrelloServerToken,
+    ];
+
+    /// Section of `~/.selfassessment` the credential is stored in by the file backend.
+    fn section(self) -> &'static str {
+        match self {
+            AuthType::GitHubAuthToken => "GitHub",
+            AuthType::TrelloApiKey | AuthType::TrelloServerToken => "Trello",
+        }
+    }
+
+    /// Name of the credential, both in `~/.selfassessment` and in the keyring.
+    fn key(self) -> &'static str {
+        match self {
+            AuthType::GitHubAuthToken => "GITHUB_TOKEN",
+            AuthType::TrelloApiKey => "TRELLO_KEY",
+            AuthType::TrelloServerToken => "TRELLO_TOKEN",
+        }
+    }
+
+    /// Environment variable the credential can be passed in, e.g. in CI.
+    pub fn env_var(self) -> &'static str {
+        match self {
+            AuthType::GitHubAuthToken => "GITHUB_TOKEN",
+            AuthType::TrelloApiKey => "SELF_ASSESSMENT_TRELLO_KEY",
+            AuthType::TrelloServerToken => "SELF_ASSESSMENT_TRELLO_TOKEN",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            AuthType::GitHubAuthToken => "GitHub personal access token",
+            AuthType::TrelloApiKey => "Trello API key token",
+            AuthType::TrelloServerToken => "Trello server token",
+        }
+    }
+}
+
+/// Where a credential was found.
+#[derive(Debug, Clone)]
+pub enum CredentialSource {
+    Stdin,
+    Environment(&'static str),
+    Store(String),
+}
+
+impl Display for CredentialSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CredentialSource::Stdin => write!(f, "standard input"),
+            CredentialSource::Environment(var) => write!(f, "the {} environment variable", var),
+            CredentialSource::Store(store) => write!(f, "{}", store),
+        }
+    }
+}
+
+/// A credential and where it was found.
+#[derive(Debug, Clone)]
+pub struct Credential {
+    pub value: String,
+    pub source: CredentialSource,
+}
+
+/// Somewhere to keep GitHub and Trello credentials.
+pub trait CredentialStore {
+    /// Name of the store, shown in console messages.
+    fn name(&self) -> String;
+    fn get(&self, credential: AuthType) -> anyhow::Result<Option<String>>;
+    fn set(&self, credential: AuthType, value: &str) -> anyhow::Result<()>;
+    fn delete(&self, credential: AuthType) -> anyhow::Result<()>;
+}
+
+/// The OS keyring: the Secret Service on Linux, the Keychain on macOS
+/// and the Credential Manager on Windows.
+pub struct KeyringStore {
+    profile: Option<String>,
+}
+
+impl KeyringStore {
+    /// The keyring, if there is one that can be reached.
+    /// On Linux this needs a running Secret Service, e.g. GNOME Keyring or KWallet.
+    pub fn available(profile: Option<&str>) -> Option<Self> {
+        let store = KeyringStore {
+            profile: profile.map(|p| p.to_string()),
+        };
+        match store
+            .entry(AuthType::GitHubAuthToken)
+            .and_then(|e| e.get_password())
+        {
+            Ok(_) | Err(keyring::Error::NoEntry) => Some(store),
+            Err(_) => None,
+        }
+    }
+
+    fn entry(&self, credential: AuthType) -> keyring::Result<Entry> {
+        Entry::new(
+            KEYRING_SERVICE,
+            &profile_name(credential.key(), self.profile.as_deref()),
+        )
+    }
+}
+
+impl CredentialStore for KeyringStore {
+    fn name(&self) -> String {
+        "the OS keyring".to_string()
+    }
+
+    fn get(&self, credential: AuthType) -> anyhow::Result<Option<String>> {
+        match self.entry(credential).and_then(|e| e.get_password()) {
+            Ok(value) => Ok(Some(value)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(err) => Err(err).with_context(|| {
+                format!(
+                    "Couldn't read the {} from the keyring",
+                    credential.description()
+                )
+            }),
+        }
+    }
+
+    fn set(&self, credential: AuthType, value: &str) -> anyhow::Result<()> {
+        self.entry(credential)
+            .and_then(|e| e.set_password(value))
+            .with_context(|| {
+                format!(
+                    "Couldn't save the {} to the keyring",
+                    credential.description()
+                )
+            })
+    }
+
+    fn delete(&self, credential: AuthType) -> anyhow::Result<()> {
+        match self.entry(credential).and_then(|e| e.delete_credential()) {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(err) => Err(err).with_context(|| {
+                format!(
+                    "Couldn't remove the {} from the keyring",
+                    credential.description()
+                )
+            }),
+        }
+    }
+}
+
+/// `~/.selfassessment`, readable only by the user.
+pub struct FileStore {
+    profile: Option<String>,
+}
+
+impl FileStore {
+    pub fn new(profile: Option<&str>) -> Self {
+        FileStore {
+            profile: profile.map(|p| p.to_string()),
+        }
+    }
+
+    fn section(&self, credential: AuthType) -> String {
+        profile_name(credential.section(), self.profile.as_deref())
+    }
+}
+
+impl CredentialStore for FileStore {
+    fn name(&self) -> String {
+        store_path()
+    }
+
+    fn get(&self, credential: AuthType) -> anyhow::Result<Option<String>> {
+        Ok(load_store()
+            .section(Some(self.section(credential)))
+            .and_then(|s| s.get(credential.key()))
+            .map(|t| t.to_string()))
+    }
+
+    fn set(&self, credential: AuthType, value: &str) -> anyhow::Result<()> {
+        let mut store = load_store();
+        store
+            .with_section(Some(self.section(credential)))
+            .set(credential.key(), value);
+        write_store(&store)
+    }
+
+    fn delete(&self, credential: AuthType) -> anyhow::Result<()> {
+        let mut store = load_store();
+        store.delete_from(Some(self.section(credential)), credential.key());
+        write_store(&store)
+    }
+}
+
+/// The store credentials of the selected profile are saved to.
+pub fn credential_store() -> anyhow::Result<Box<dyn CredentialStore>> {
+    credential_store_for(profile())
+}
+
+/// The store credentials of a profile are saved to, chosen by the `CREDENTIAL_STORE` setting
+/// (`keyring` or `file`). By default the keyring is used when there is one, and the file otherwise.
+pub fn credential_store_for(profile: Option<&str>) -> anyhow::Result<Box<dyn CredentialStore>> {
+    match get_profile_setting("CREDENTIAL_STORE", profile).as_deref() {
+        Some("keyring") => match KeyringStore::available(profile) {
+            Some(keyring) => Ok(Box::new(keyring)),
+            None => bail!("CREDENTIAL_STORE is set to keyring, but no OS keyring could be reached"),
+        },
+        Some("file") => Ok(Box::new(FileStore::new(profile))),
+        Some(other) => bail!(
+            "Unknown CREDENTIAL_STORE \"{}\", expected keyring or file",
+            other
+        ),
+        None => Ok(match KeyringStore::available(profile) {
+            Some(keyring) => Box::new(keyring),
+            None => Box::new(FileStore::new(profile)),
+        }),
+    }
+}
 
 /// Set GitHub and Trello credentials.
-pub fn set_credentials(
-    store: &mut Ini,
-    store_path: String,
-    section: String,
-    key: String,
-    value: String,
-) -> anyhow::Result<()> {
-    let key_msg = match key.as_str() {
-        "GITHUB_TOKEN" => "GitHub personal access token",
-        "TRELLO_KEY" => "Trello API key token",
-        "TRELLO_TOKEN" => "Trello server token",
-        _ => "",
-    };
-    store.with_section(Some(section)).set(key, value);
-    if store.write_to_file(store_path).is_ok() {
-        println!("[self-assessment] 🔑 {key_msg} set successfully.");
+pub fn set_credentials(credential: AuthType, value: String) -> anyhow::Result<()> {
+    let store = credential_store()?;
+    store.set(credential, &value)?;
+    if let Some(profile) = profile() {
+        register_profile(profile)?;
     }
+    println!(
+        "[self-assessment] 🔑 {} saved to {}{}.",
+        credential.description(),
+        store.name(),
+        profile()
+            .map(|p| format!(" for the {} profile", p))
+            .unwrap_or_default()
+    );
     Ok(())
 }
 
-/// Load credentials from disk.
-/// Credentials live in `~/.selfassessment`
-pub fn get_auth_token(flag: AuthType) -> Option<String> {
-    let credential_store_path = format!("{}/.selfassessment", shellexpand::tilde("~/"));
-    let mut credential_store = match Ini::load_from_file(credential_store_path) {
-        Ok(ini) => ini,
-        Err(_) => Ini::new(),
+/// Find a credential. In order of precedence, credentials are read from:
+/// 1. their environment variable, e.g. `GITHUB_TOKEN`
+/// 2. the credential store
+/// 3. `~/.selfassessment`, for credentials saved in plain text before the keyring was used,
+///    with a reminder to migrate them
+pub fn get_auth_token(credential: AuthType) -> anyhow::Result<Option<Credential>> {
+    static MIGRATE_REMINDER: Once = Once::new();
+
+    if let Some(value) = std::env::var(credential.env_var())
+        .ok()
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+    {
+        return Ok(Some(Credential {
+            value,
+            source: CredentialSource::Environment(credential.env_var()),
+        }));
+    }
+
+    let store = credential_store()?;
+    if let Some(value) = store.get(credential)? {
+        return Ok(Some(Credential {
+            value,
+            source: CredentialSource::Store(store.name()),
+        }));
+    }
+
+    let legacy_value = FileStore::new(profile()).get(credential)?;
+    if legacy_value.is_some() {
+        MIGRATE_REMINDER.call_once(|| {
+            println!(
+                "[self-assessment] 🔓 Your credentials are stored in plain text in {}. Run `self-assessment migrate-credentials` to move them to {}.",
+                store_path(),
+                store.name()
+            );
+        });
+    }
+    Ok(legacy_value.map(|value| Credential {
+        value,
+        source: CredentialSource::Store(store_path()),
+    }))
+}
+
+/// Read a secret from the first line of standard input, so it doesn't end up in the shell history.
+pub fn read_secret_from_stdin(credential: AuthType) -> anyhow::Result<Credential> {
+    let stdin = std::io::stdin();
+    if stdin.is_terminal() {
+        eprintln!(
+            "[self-assessment] 🔑 Paste your {} and press Enter:",
+            credential.description()
+        );
+    }
+
+    let mut value = String::new();
+    stdin
+        .lock()
+        .read_line(&mut value)
+        .context("Couldn't read from standard input")?;
+    let value = value.trim().to_string();
+    if value.is_empty() {
+        bail!(
+            "No {} was passed on standard input",
+            credential.description()
+        );
+    }
+
+    Ok(Credential {
+        value,
+        source: CredentialSource::Stdin,
+    })
+}
+
+/// Move credentials saved in `~/.selfassessment` into the credential store.
+pub fn migrate_credentials() -> anyhow::Result<()> {
+    let store = credential_store()?;
+    let file_store = FileStore::new(profile());
+    if store.name() == file_store.name() {
+        // Rewriting the file restricts its permissions
+        write_store(&load_store())?;
+        println!(
+            "[self-assessment] 🔒 No OS keyring available. Credentials stay in {}, now only readable by you.",
+            store_path()
+        );
+        return Ok(());
+    }
+
+    let mut migrated = 0;
+    for credential in AuthType::ALL {
+        if let Some(value) = file_store.get(credential)? {
+            store.set(credential, &value)?;
+            file_store.delete(credential)?;
+            println!(
+                "[self-assessment] 🔒 {} moved to {}.",
+                credential.description(),
+                store.name()
+            );
+            migrated += 1;
+        }
+    }
+
+    if migrated == 0 {
+        println!(
+            "[self-assessment] ✅ No credentials left to migrate in {}.",
+            store_path()
+        );
+    }
+    Ok(())
+}
+
+/// Remove every credential saved for a profile.
+pub fn delete_credentials(profile: Option<&str>) -> anyhow::Result<()> {
+    let store = credential_store_for(profile)?;
+    for credential in AuthType::ALL {
+        store.delete(credential)?;
+    }
+    Ok(())
+}
+
+/// Remove saved credentials, optionally revoking the Trello server token first.
+pub async fn logout(service: LogoutService, revoke: bool) -> anyhow::Result<()> {
+    let credentials: &[AuthType] = match service {
+        LogoutService::Github => &[AuthType::GitHubAuthToken],
+        LogoutService::Trello => &[AuthType::TrelloApiKey, AuthType::TrelloServerToken],
+        LogoutService::All => &AuthType::ALL,
     };
+    let store = credential_store()?;
+    // Credentials saved before the keyring was used may still be in the file
+    let file_store = FileStore::new(profile());
+
+    if revoke && credentials.contains(&AuthType::TrelloServerToken) {
+        let find = |credential| -> anyhow::Result<Option<String>> {
+            Ok(store.get(credential)?.or(file_store.get(credential)?))
+        };
+        match (
+            find(AuthType::TrelloApiKey)?,
+            find(AuthType::TrelloServerToken)?,
+        ) {
+            (Some(key), Some(token)) => {
+                revoke_trello_token(&reqwest::Client::new(), &key, &token)
+                    .await
+                    .context("Couldn't revoke the Trello server token, so nothing was removed")?;
+                println!("[self-assessment] 🔒 Trello server token revoked.");
+            }
+            _ => println!(
+                "[self-assessment] ⏩ No Trello server token saved, so there is nothing to revoke."
+            ),
+        }
+    }
+    if revoke && credentials.contains(&AuthType::GitHubAuthToken) {
+        let tokens_url = GithubHost::from_settings()
+            .map(|host| host.tokens_url())
+            .unwrap_or_else(|_| format!("{}/settings/tokens", DEFAULT_GITHUB_URL));
+        println!("[self-assessment] ℹ️ GitHub personal access tokens can't be revoked by this tool. Delete the token at {}", tokens_url);
+    }
+
+    for &credential in credentials {
+        let saved = store.get(credential)?.is_some() || file_store.get(credential)?.is_some();
+        store.delete(credential)?;
+        file_store.delete(credential)?;
+        if saved {
+            println!("[self-assessment] 🗑️ {} removed.", credential.description());
+        }
+
+        if std::env::var(credential.env_var()).is_ok_and(|v| !v.trim().is_empty()) {
+            println!(
+                "[self-assessment] ⚠️ The {} environment variable is still set, so the {} will still be used.",
+                credential.env_var(),
+                credential.description()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::any::Any;
+    use std::collections::HashMap;
+    use std::sync::{Mutex, MutexGuard, Once};
+
+    use keyring::credential::{Credential, CredentialApi, CredentialBuilderApi};
+    use keyring::Error;
+
+    use super::*;
+
+    type Passwords = HashMap<(String, String), Vec<u8>>;
+    /// Passwords saved to the in-memory keyring, by service and user.
+    static KEYRING: Mutex<Option<Passwords>> = Mutex::new(None);
+    /// Tests share the keyring and `~/.selfassessment`, so they run one at a time.
+    static SERIAL: Mutex<()> = Mutex::new(());
+
+    /// An entry of the in-memory keyring. Unlike `keyring::mock`, what is saved through one
+    /// entry can be read through another, as with a real keyring.
+    #[derive(Debug)]
+    struct MemoryCredential {
+        key: (String, String),
+    }
+
+    impl CredentialApi for MemoryCredential {
+        fn set_secret(&self, secret: &[u8]) -> keyring::Result<()> {
+            let mut keyring = KEYRING.lock().unwrap();
+            keyring
+                .get_or_insert_with(HashMap::new)
+                .insert(self.key.clone(), secret.to_vec());
+            Ok(())
+        }
+
+        fn get_secret(&self) -> keyring::Result<Vec<u8>> {
+            let keyring = KEYRING.lock().unwrap();
+            keyring
+                .as_ref()
+                .and_then(|k| k.get(&self.key).cloned())
+                .ok_or(Error::NoEntry)
+        }
+
+        fn delete_credential(&self) -> keyring::Result<()> {
+            let mut keyring = KEYRING.lock().unwrap();
+            keyring
+                .as_mut()
+                .and_then(|k| k.remove(&self.key))
+                .map(|_| ())
+                .ok_or(Error::NoEntry)
+        }
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+    }
+
+    struct MemoryCredentialBuilder;
+
+    impl CredentialBuilderApi for MemoryCredentialBuilder {
+        fn build(
+            &self,
+            _target: Option<&str>,
+            service: &str,
+            user: &str,
+        ) -> keyring::Result<Box<Credential>> {
+            Ok(Box::new(MemoryCredential {
+                key: (service.to_string(), user.to_string()),
+            }))
+        }
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+    }
+
+    /// Use an empty in-memory keyring, and an empty `~/.selfassessment` in a temporary home
+    /// directory, until the returned guard is dropped.
+    fn isolate() -> MutexGuard<'static, ()> {
+        static SETUP: Once = Once::new();
+        let guard = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+        SETUP.call_once(|| {
+            let home = std::env::temp_dir().join(format!("self-assessment-{}", std::process::id()));
+            std::fs::create_dir_all(&home).unwrap();
+            std::env::set_var("HOME", home);
+            keyring::set_default_credential_builder(Box::new(MemoryCredentialBuilder));
+        });
+        *KEYRING.lock().unwrap() = None;
+        let _ = std::fs::remove_file(store_path());
+        guard
+    }
+
+    #[test]
+    fn keyring_store_gets_sets_and_deletes_credentials() {
+        let _guard = isolate();
+        let store = KeyringStore::available(None).expect("the keyring should be reachable");
+
+        assert_eq!(store.get(AuthType::GitHubAuthToken).unwrap(), None);
+        store.set(AuthType::GitHubAuthToken, "ghp_123").unwrap();
+        assert_eq!(
+            store.get(AuthType::GitHubAuthToken).unwrap().as_deref(),
+            Some("ghp_123")
+        );
+        assert_eq!(store.get(AuthType::TrelloApiKey).unwrap(), None);
+
+        store.delete(AuthType::GitHubAuthToken).unwrap();
+        assert_eq!(store.get(AuthType::GitHubAuthToken).unwrap(), None);
+        // Deleting a credential that isn't saved is not an error
+        store.delete(AuthType::GitHubAuthToken).unwrap();
+    }
+
+    #[test]
+    fn keyring_store_keeps_profiles_apart() {
+        let _guard = isolate();
+        let default = KeyringStore::available(None).unwrap();
+        let work = KeyringStore::available(Some("work")).unwrap();
+
+        default.set(AuthType::GitHubAuthToken, "personal").unwrap();
+        work.set(AuthType::GitHubAuthToken, "work").unwrap();
+        assert_eq!(
+            default.get(AuthType::GitHubAuthToken).unwrap().as_deref(),
+            Some("personal")
+        );
+        assert_eq!(
+            work.get(AuthType::GitHubAuthToken).unwrap().as_deref(),
+            Some("work")
+        );
+
+        work.delete(AuthType::GitHubAuthToken).unwrap();
+        assert!(default.get(AuthType::GitHubAuthToken).unwrap().is_some());
+    }
+
+    #[test]
+    fn migrate_credentials_moves_them_from_the_file_to_the_keyring() {
+        let _guard = isolate();
+        let file_store = FileStore::new(None);
+        file_store
+            .set(AuthType::GitHubAuthToken, "ghp_123")
+            .unwrap();
+        file_store.set(AuthType::TrelloApiKey, "key").unwrap();
+        let mut store = load_store();
+        store.with_section(Some("Settings")).set("ORGS", "guardian");
+        write_store(&store).unwrap();
+
+        migrate_credentials().unwrap();
+
+        let keyring = KeyringStore::available(None).unwrap();
+        assert_eq!(
+            keyring.get(AuthType::GitHubAuthToken).unwrap().as_deref(),
+            Some("ghp_123")
+        );
+        assert_eq!(
+            keyring.get(AuthType::TrelloApiKey).unwrap().as_deref(),
+            Some("key")
+        );
+        assert_eq!(keyring.get(AuthType::TrelloServerToken).unwrap(), None);
+
+        for credential in AuthType::ALL {
+            assert_eq!(file_store.get(credential).unwrap(), None);
+        }
+        // Settings stay in the file
+        assert_eq!(
+            load_store().get_from(Some("Settings"), "ORGS"),
+            Some("guardian")
+        );
+    }
+
+    #[test]
+    fn credential_store_follows_the_setting_of_each_profile() {
+        let _guard = isolate();
+        let mut store = load_store();
+        store
+            .with_section(Some("Settings:ci"))
+            .set("CREDENTIAL_STORE", "file");
+        write_store(&store).unwrap();
 
-    match flag {
-        AuthType::GitHubAuthToken => credential_store
-            .with_section(Some("GitHub"))
-            .get("GITHUB_TOKEN")
-            .map(|t| t.to_string()),
-        AuthType::TrelloApiKey => credential_store
-            .with_section(Some("Trello"))
-            .get("TRELLO_KEY")
-            .map(|t| t.to_string()),
-        AuthType::TrelloServerToken => credential_store
-            .with_section(Some("Trello"))
-            .get("TRELLO_TOKEN")
-            .map(|t| t.to_string()),
+        assert_eq!(credential_store_for(None).unwrap().name(), "the OS keyring");
+        assert_eq!(
+            credential_store_for(Some("ci")).unwrap().name(),
+            store_path()
+        );
     }
 }
@@ -0,0 +1,334 @@
+use std::fmt::Display;
+
+use anyhow::{anyhow, bail, Context};
+use chrono::{Datelike, Duration, Months, NaiveDate};
+
+use crate::config::get_review_cycle;
+
+/// An inclusive range of days. A missing end means the range is open on that side.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DateRange {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+impl DateRange {
+    pub fn new(from: Option<NaiveDate>, to: Option<NaiveDate>) -> anyhow::Result<Self> {
+        if let (Some(from), Some(to)) = (from, to) {
+            if from > to {
+                bail!("The start date ({}) is after the end date ({})", from, to);
+            }
+        }
+        Ok(DateRange { from, to })
+    }
+
+    pub fn contains(&self, date: NaiveDate) -> bool {
+        self.from.is_none_or(|from| date >= from) && self.to.is_none_or(|to| date <= to)
+    }
+}
+
+/// Formats as a GitHub search range, e.g. `2024-01-01..*`.
+impl Display for DateRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let end = |d: Option<NaiveDate>| d.map_or("*".to_string(), |d| d.to_string());
+        write!(f, "{}..{}", end(self.from), end(self.to))
+    }
+}
+
+/// Parse a YYYY-MM-DD date passed on the command line.
+pub fn parse_date(date: &str) -> anyhow::Result<NaiveDate> {
+    NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d")
+        .with_context(|| format!("\"{}\" is not a valid date, expected YYYY-MM-DD", date))
+}
+
+/// Work out the date range of a report from the `--from`, `--to`, `--period` and `--since` flags.
+pub fn resolve_date_range(
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    period: Option<&str>,
+    since: Option<&str>,
+    today: NaiveDate,
+) -> anyhow::Result<DateRange> {
+    match (period, since) {
+        (Some(period), _) => resolve_period(period, today),
+        (None, Some(since)) => DateRange::new(Some(parse_since(since, today)?), to),
+        (None, None) => DateRange::new(from, to),
+    }
+}
+
+/// Resolve a named period into a date range. Supported periods are:
+/// - `this-month`, `last-month`, `this-quarter`, `last-quarter`, `this-year` and `last-year`
+/// - a year, e.g. `2025`
+/// - a half year, e.g. `H1-2026`
+/// - a quarter, e.g. `Q3-2025`
+/// - a review cycle defined in the `[Cycles]` section of `~/.selfassessment`
+pub fn resolve_period(period: &str, today: NaiveDate) -> anyhow::Result<DateRange> {
+    if let Some(cycle) = get_review_cycle(period) {
+        return parse_review_cycle(&cycle)
+            .with_context(|| format!("Invalid dates for the \"{}\" review cycle", period));
+    }
+
+    let quarter_start = |year: i32, quarter: u32| ymd(year, (quarter - 1) * 3 + 1, 1);
+    let this_quarter = (today.month() - 1) / 3 + 1;
+
+    let (from, to) = match period.to_lowercase().as_str() {
+        "this-month" => {
+            let from = ymd(today.year(), today.month(), 1)?;
+            (from, end_of(from, 1))
+        }
+        "last-month" => {
+            let from = ymd(today.year(), today.month(), 1)? - Months::new(1);
+            (from, end_of(from, 1))
+        }
+        "this-quarter" => {
+            let from = quarter_start(today.year(), this_quarter)?;
+            (from, end_of(from, 3))
+        }
+        "last-quarter" => {
+            let from = quarter_start(today.year(), this_quarter)? - Months::new(3);
+            (from, end_of(from, 3))
+        }
+        "this-year" => (ymd(today.year(), 1, 1)?, ymd(today.year(), 12, 31)?),
+        "last-year" => (ymd(today.year() - 1, 1, 1)?, ymd(today.year() - 1, 12, 31)?),
+        other => match other.split_once('-') {
+            Some(("h1", year)) => (ymd(parse_year(year)?, 1, 1)?, ymd(parse_year(year)?, 6, 30)?),
+            Some(("h2", year)) => (ymd(parse_year(year)?, 7, 1)?, ymd(parse_year(year)?, 12, 31)?),
+            Some((quarter, year)) if matches!(quarter, "q1" | "q2" | "q3" | "q4") => {
+                let from = quarter_start(parse_year(year)?, quarter[1..].parse()?)?;
+                (from, end_of(from, 3))
+            }
+            None if other.len() == 4 => (ymd(parse_year(other)?, 1, 1)?, ymd(parse_year(other)?, 12, 31)?),
+            _ => bail!(
+                "Unknown period \"{}\". Use this-month, last-month, this-quarter, last-quarter, this-year, last-year, a year (2025), a half (H1-2026), a quarter (Q3-2025) or a review cycle from the [Cycles] section of ~/.selfassessment",
+                period
+            ),
+        },
+    };
+
+    DateRange::new(Some(from), Some(to))
+}
+
+/// Parse a relative start date such as `30d`, `2w`, `6m` or `1y`, counting back from today.
+pub fn parse_since(since: &str, today: NaiveDate) -> anyhow::Result<NaiveDate> {
+    let since = since.trim().to_lowercase();
+    let invalid = || {
+        anyhow!(
+            "\"{}\" is not a valid duration, expected a number followed by d, w, m or y (e.g. 6m)",
+            since
+        )
+    };
+
+    let (unit_index, unit) = since.char_indices().next_back().ok_or_else(invalid)?;
+    let amount: u32 = since[..unit_index].parse().map_err(|_| invalid())?;
+
+    let date = match unit {
+        'd' => today.checked_sub_signed(Duration::days(amount.into())),
+        'w' => today.checked_sub_signed(Duration::weeks(amount.into())),
+        'm' => today.checked_sub_months(Months::new(amount)),
+        'y' => today.checked_sub_months(Months::new(amount.checked_mul(12).ok_or_else(invalid)?)),
+        _ => return Err(invalid()),
+    };
+    date.ok_or_else(invalid)
+}
+
+/// Parse a review cycle setting, e.g. `2026-01-01..2026-06-30`.
+fn parse_review_cycle(cycle: &str) -> anyhow::Result<DateRange> {
+    let (from, to) = cycle
+        .split_once("..")
+        .ok_or_else(|| anyhow!("expected YYYY-MM-DD..YYYY-MM-DD, got \"{}\"", cycle))?;
+    DateRange::new(Some(parse_date(from)?), Some(parse_date(to)?))
+}
+
+/// Parse a four-digit year, e.g. `2025`.
+fn parse_year(year: &str) -> anyhow::Result<i32> {
+    if year.len() != 4 || !year.bytes().all(|b| b.is_ascii_digit()) {
+        bail!("\"{}\" is not a valid year, expected four digits", year);
+    }
+    Ok(year.parse()?)
+}
+
+fn ymd(year: i32, month: u32, day: u32) -> anyhow::Result<NaiveDate> {
+    NaiveDate::from_ymd_opt(year, month, day)
+        .ok_or_else(|| anyhow!("{}-{:02}-{:02} is not a valid date", year, month, day))
+}
+
+/// Last day of the period of `months` months starting on `from`.
+fn end_of(from: NaiveDate, months: u32) -> NaiveDate {
+    (from + Months::new(months)).pred_opt().unwrap_or(from)
+}
+
+/// Describe a duration in its largest whole unit, e.g. "45 minutes", "3 hours" or "2 days".
+pub fn describe_duration(duration: Duration) -> String {
+    let (count, unit) = match duration {
+        d if d.num_days() >= 1 => (d.num_days(), "day"),
+        d if d.num_hours() >= 1 => (d.num_hours(), "hour"),
+        d => (d.num_minutes().max(0), "minute"),
+    };
+    format!("{} {}{}", count, unit, if count == 1 { "" } else { "s" })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(date: &str) -> NaiveDate {
+        parse_date(date).unwrap()
+    }
+
+    fn range(from: &str, to: &str) -> DateRange {
+        DateRange::new(Some(date(from)), Some(date(to))).unwrap()
+    }
+
+    #[test]
+    fn since_counts_back_from_today() {
+        let today = date("2026-03-31");
+
+        assert_eq!(parse_since("30d", today).unwrap(), date("2026-03-01"));
+        assert_eq!(parse_since("2W", today).unwrap(), date("2026-03-17"));
+        assert_eq!(parse_since(" 1m ", today).unwrap(), date("2026-02-28"));
+        assert_eq!(parse_since("1y", today).unwrap(), date("2025-03-31"));
+        assert_eq!(parse_since("0d", today).unwrap(), today);
+        assert_eq!(parse_since("0y", today).unwrap(), today);
+    }
+
+    #[test]
+    fn since_rejects_invalid_durations() {
+        let today = date("2026-03-31");
+
+        for since in [
+            "",
+            "d",
+            "6",
+            "6x",
+            "-6m",
+            "6 m",
+            "6é",
+            "é",
+            "6mé",
+            "٣d",
+            "4294967296d",
+            "400000000y",
+            "3000000000d",
+            "4000000000w",
+        ] {
+            assert!(parse_since(since, today).is_err(), "{}", since);
+        }
+    }
+
+    #[test]
+    fn presets_are_relative_to_today() {
+        let today = date("2026-02-15");
+        let cases = [
+            ("this-month", range("2026-02-01", "2026-02-28")),
+            ("last-month", range("2026-01-01", "2026-01-31")),
+            ("this-quarter", range("2026-01-01", "2026-03-31")),
+            ("last-quarter", range("2025-10-01", "2025-12-31")),
+            ("this-year", range("2026-01-01", "2026-12-31")),
+            ("last-year", range("2025-01-01", "2025-12-31")),
+            ("This-Month", range("2026-02-01", "2026-02-28")),
+        ];
+
+        for (period, expected) in cases {
+            assert_eq!(
+                resolve_period(period, today).unwrap(),
+                expected,
+                "{}",
+                period
+            );
+        }
+    }
+
+    #[test]
+    fn presets_wrap_around_the_year() {
+        let today = date("2024-01-10");
+
+        assert_eq!(
+            resolve_period("last-month", today).unwrap(),
+            range("2023-12-01", "2023-12-31")
+        );
+        assert_eq!(
+            resolve_period("last-quarter", today).unwrap(),
+            range("2023-10-01", "2023-12-31")
+        );
+        assert_eq!(
+            resolve_period("this-month", date("2024-02-29")).unwrap(),
+            range("2024-02-01", "2024-02-29")
+        );
+        assert_eq!(
+            resolve_period("this-quarter", date("2024-12-31")).unwrap(),
+            range("2024-10-01", "2024-12-31")
+        );
+    }
+
+    #[test]
+    fn named_periods_ignore_today() {
+        let today = date("2026-02-15");
+        let cases = [
+            ("2025", range("2025-01-01", "2025-12-31")),
+            ("H1-2026", range("2026-01-01", "2026-06-30")),
+            ("h2-2026", range("2026-07-01", "2026-12-31")),
+            ("Q1-2025", range("2025-01-01", "2025-03-31")),
+            ("q2-2025", range("2025-04-01", "2025-06-30")),
+            ("Q3-2025", range("2025-07-01", "2025-09-30")),
+            ("Q4-2025", range("2025-10-01", "2025-12-31")),
+        ];
+
+        for (period, expected) in cases {
+            assert_eq!(
+                resolve_period(period, today).unwrap(),
+                expected,
+                "{}",
+                period
+            );
+        }
+    }
+
+    #[test]
+    fn named_periods_need_a_four_digit_year() {
+        let today = date("2026-02-15");
+
+        for period in [
+            "H1--5",
+            "Q1--2025",
+            "H2-+202",
+            "Q3-25",
+            "Q4-20255",
+            "+202",
+            "-202",
+            "H1-２０２６",
+            "Q5-2025",
+            "H3-2025",
+            "next-month",
+            "",
+        ] {
+            assert!(resolve_period(period, today).is_err(), "{}", period);
+        }
+    }
+
+    #[test]
+    fn review_cycles_are_inclusive_date_ranges() {
+        assert_eq!(
+            parse_review_cycle("2026-01-01..2026-06-30").unwrap(),
+            range("2026-01-01", "2026-06-30")
+        );
+        assert_eq!(
+            parse_review_cycle(" 2026-01-01 .. 2026-06-30 ").unwrap(),
+            range("2026-01-01", "2026-06-30")
+        );
+        assert_eq!(
+            parse_review_cycle("2026-03-01..2026-03-01").unwrap(),
+            range("2026-03-01", "2026-03-01")
+        );
+
+        for cycle in [
+            "2026-06-30..2026-01-01",
+            "2026-01-01",
+            "2026-01-01..",
+            "2026-01-01...2026-06-30",
+            "2026-02-30..2026-06-30",
+            "H1-2026",
+        ] {
+            assert!(parse_review_cycle(cycle).is_err(), "{}", cycle);
+        }
+    }
+}
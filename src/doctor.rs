@@ -0,0 +1,538 @@
+use anyhow::{bail, Context};
+use chrono::{DateTime, Local, Utc};
+use octocrab::Octocrab;
+use reqwest::header::HeaderMap;
+use serde::Deserialize;
+
+use crate::cache::Cache;
+use crate::cli::AuthType;
+use crate::config::{github_orgs, store_path};
+use crate::credentials::{credential_store, get_auth_token};
+use crate::github_host::GithubHost;
+
+/// What GitHub says about a personal access token.
+pub struct GithubTokenCheck {
+    pub login: String,
+    /// Scopes of a classic token. Fine-grained tokens don't have scopes.
+    pub scopes: Option<Vec<String>>,
+    pub expires_at: Option<String>,
+    pub orgs: Vec<OrgAccess>,
+    /// Where the token can be managed.
+    pub tokens_url: String,
+}
+
+/// Whether a token can be used to read an organisation's repositories.
+pub enum OrgAccess {
+    Authorised(String),
+    /// The organisation uses SAML single sign-on and the token hasn't been authorised for it.
+    SsoRequired {
+        org: String,
+        url: Option<String>,
+    },
+    NotFound(String),
+    /// Any other failure, e.g. an insufficient token or an outage, with GitHub's explanation.
+    Failed {
+        org: String,
+        status: u16,
+        message: String,
+    },
+}
+
+/// What Trello says about an API key and server token.
+pub struct TrelloTokenCheck {
+    pub full_name: String,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TrelloMember {
+    full_name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TrelloToken {
+    date_expires: Option<DateTime<Utc>>,
+}
+
+/// Check a GitHub token against the API, including whether it is authorised for each organisation.
+pub async fn verify_github_token(
+    host: &GithubHost,
+    token: &str,
+    orgs: &[String],
+) -> anyhow::Result<GithubTokenCheck> {
+    let octocrab = host.octocrab(token)?;
+
+    let response = octocrab
+        ._get("/user")
+        .await
+        .context("Couldn't reach GitHub")?;
+    let status = response.status();
+    let headers = response.headers().clone();
+    let body = octocrab.body_to_string(response).await?;
+    if status.as_u16() == 401 {
+        bail!("GitHub rejected the token. It may be mistyped, expired or revoked.");
+    }
+    if !status.is_success() {
+        bail!("GitHub returned {} for /user: {}", status, body);
+    }
+    let user: octocrab::models::Author = serde_json::from_str(&body)?;
+
+    let scopes = header(&headers, "x-oauth-scopes").map(|scopes| {
+        scopes
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect()
+    });
+    let expires_at = header(&headers, "github-authentication-token-expiration");
+
+    let mut org_access = vec![];
+    for org in orgs {
+        org_access.push(check_org_access(&octocrab, org).await?);
+    }
+
+    Ok(GithubTokenCheck {
+        login: user.login,
+        scopes,
+        expires_at,
+        orgs: org_access,
+        tokens_url: host.tokens_url(),
+    })
+}
+
+/// Check whether a token can read an organisation's repositories. GitHub hides the repositories
+/// of organisations a token isn't authorised for with SAML single sign-on from lists, only
+/// flagging the list as partial, so the organisation's id is looked up first to recognise it.
+async fn check_org_access(octocrab: &Octocrab, org: &str) -> anyhow::Result<OrgAccess> {
+    let reach_error = || format!("Couldn't reach GitHub to check access to {}", org);
+
+    let response = octocrab
+        ._get(format!("/orgs/{}", org))
+        .await
+        .with_context(reach_error)?;
+    let status = response.status();
+    if let Some(SsoHeader::Required { url }) = sso_header(response.headers()) {
+        return Ok(OrgAccess::SsoRequired {
+            org: org.to_string(),
+            url,
+        });
+    }
+    let body = octocrab.body_to_string(response).await?;
+    let org_id = match status.as_u16() {
+        _ if status.is_success() => {
+            serde_json::from_str::<serde_json::Value>(&body)?["id"].as_u64()
+        }
+        404 => return Ok(OrgAccess::NotFound(org.to_string())),
+        status => return Ok(failed_org_access(org, status, body)),
+    };
+
+    let response = octocrab
+        ._get(format!("/orgs/{}/repos?per_page=1", org))
+        .await
+        .with_context(reach_error)?;
+    let status = response.status();
+    Ok(match (status.as_u16(), sso_header(response.headers())) {
+        (_, Some(SsoHeader::Required { url })) => OrgAccess::SsoRequired {
+            org: org.to_string(),
+            url,
+        },
+        (_, Some(SsoHeader::PartialResults(org_ids)))
+            if org_id.is_some_and(|id| org_ids.contains(&id)) =>
+        {
+            OrgAccess::SsoRequired {
+                org: org.to_string(),
+                url: None,
+            }
+        }
+        _ if status.is_success() => OrgAccess::Authorised(org.to_string()),
+        (404, _) => OrgAccess::NotFound(org.to_string()),
+        (status, _) => {
+            let body = octocrab.body_to_string(response).await.unwrap_or_default();
+            failed_org_access(org, status, body)
+        }
+    })
+}
+
+fn failed_org_access(org: &str, status: u16, body: String) -> OrgAccess {
+    // GitHub explains errors in a JSON "message" field
+    let message = serde_json::from_str::<serde_json::Value>(&body)
+        .ok()
+        .and_then(|v| v["message"].as_str().map(|m| m.to_string()))
+        .unwrap_or(body);
+    OrgAccess::Failed {
+        org: org.to_string(),
+        status,
+        message,
+    }
+}
+
+/// What the `X-GitHub-SSO` header says about SAML single sign-on.
+#[derive(Debug, PartialEq, Eq)]
+enum SsoHeader {
+    /// The token has to be authorised for the organisation, at `url` if given.
+    Required { url: Option<String> },
+    /// A list left out what belongs to these organisations, by id, because the token isn't
+    /// authorised for them.
+    PartialResults(Vec<u64>),
+}
+
+fn sso_header(headers: &HeaderMap) -> Option<SsoHeader> {
+    header(headers, "x-github-sso").and_then(|value| parse_sso_header(&value))
+}
+
+/// Parse an `X-GitHub-SSO` header, e.g. `required; url=https://github.com/orgs/x/sso?…`
+/// or `partial-results; organizations=21955855,20582480`.
+fn parse_sso_header(value: &str) -> Option<SsoHeader> {
+    let (kind, params) = value.split_once(';').unwrap_or((value, ""));
+    let param = |name: &str| {
+        params.split(';').find_map(|param| {
+            param
+                .trim()
+                .strip_prefix(name)
+                .and_then(|p| p.strip_prefix('='))
+                .map(|p| p.trim().to_string())
+        })
+    };
+    match kind.trim() {
+        "required" => Some(SsoHeader::Required { url: param("url") }),
+        "partial-results" => Some(SsoHeader::PartialResults(
+            param("organizations")
+                .unwrap_or_default()
+                .split(',')
+                .filter_map(|id| id.trim().parse().ok())
+                .collect(),
+        )),
+        _ => None,
+    }
+}
+
+/// Check a Trello API key and server token against the API.
+pub async fn verify_trello_credentials(key: &str, token: &str) -> anyhow::Result<TrelloTokenCheck> {
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(format!(
+            "https://api.trello.com/1/members/me?key={}&token={}&fields=fullName",
+            key, token
+        ))
+        .send()
+        .await
+        // The URL holds the credentials, so leave it out of the error
+        .map_err(|err| err.without_url())
+        .context("Couldn't reach Trello")?;
+    let status = response.status();
+    if !status.is_success() {
+        // Trello explains what's wrong in plain text, e.g. "invalid key"
+        let message = response.text().await.unwrap_or_default();
+        bail!(
+            "Trello rejected the API key or server token ({}: {})",
+            status,
+            message.trim()
+        );
+    }
+    let member: TrelloMember = response.json().await?;
+
+    // Only the expiry date is missing if the token can't be looked up
+    let expires_at = match client
+        .get(format!(
+            "https://api.trello.com/1/tokens/{}?key={}&token={}&fields=dateExpires",
+            token, key, token
+        ))
+        .send()
+        .await
+    {
+        Ok(response) if response.status().is_success() => response
+            .json::<TrelloToken>()
+            .await
+            .ok()
+            .and_then(|t| t.date_expires),
+        _ => None,
+    };
+
+    Ok(TrelloTokenCheck {
+        full_name: member.full_name,
+        expires_at,
+    })
+}
+
+/// An error and its underlying cause, without the backtraces some clients put in their messages.
+fn describe_error(err: &anyhow::Error) -> String {
+    let cause = err.root_cause().to_string();
+    let cause = cause.split("\n").next().unwrap_or_default();
+    match err.to_string() {
+        message if message == cause => message,
+        message => format!("{}: {}", message, cause),
+    }
+}
+
+fn header(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string())
+}
+
+/// Print the result of a GitHub token check. Returns the number of problems found.
+pub fn print_github_check(check: &GithubTokenCheck) -> usize {
+    let mut problems = 0;
+    println!(
+        "[self-assessment] ✅ The GitHub token belongs to {}.",
+        check.login
+    );
+
+    match &check.scopes {
+        Some(scopes) if scopes.iter().any(|s| s == "repo") => {
+            println!("[self-assessment] ✅ The GitHub token has the repo scope.")
+        }
+        Some(scopes) => {
+            problems += 1;
+            println!(
+                "[self-assessment] ⚠️ The GitHub token is missing the repo scope, so PRs in private repositories won't be found. Its scopes are: {}.",
+                match scopes.is_empty() {
+                    true => "none".to_string(),
+                    false => scopes.join(", "),
+                }
+            );
+        }
+        None => println!(
+            "[self-assessment] ℹ️ Scopes can't be checked for fine-grained tokens. Make sure the token can read pull requests and metadata."
+        ),
+    }
+
+    match &check.expires_at {
+        Some(expires_at) => println!(
+            "[self-assessment] 📅 The GitHub token expires on {}.",
+            expires_at
+        ),
+        None => println!("[self-assessment] 📅 The GitHub token doesn't expire."),
+    }
+
+    for access in &check.orgs {
+        match access {
+            OrgAccess::Authorised(org) => println!(
+                "[self-assessment] ✅ The GitHub token can access the {} organisation.",
+                org
+            ),
+            OrgAccess::SsoRequired { org, url } => {
+                problems += 1;
+                println!(
+                    "[self-assessment] ⚠️ The GitHub token isn't authorised for single sign-on in the {} organisation. {}",
+                    org,
+                    match url {
+                        Some(url) => format!("Authorise it at {}", url),
+                        None => format!("Click \"Configure SSO\" next to the token at {}", check.tokens_url),
+                    }
+                );
+            }
+            OrgAccess::NotFound(org) => {
+                problems += 1;
+                println!(
+                    "[self-assessment] ⚠️ The {} organisation doesn't exist, or the GitHub token can't see it.",
+                    org
+                );
+            }
+            OrgAccess::Failed {
+                org,
+                status,
+                message,
+            } => {
+                problems += 1;
+                println!(
+                    "[self-assessment] ⚠️ Couldn't check access to the {} organisation: GitHub returned {}{}",
+                    org,
+                    status,
+                    match message.trim() {
+                        "" => String::new(),
+                        message => format!(" ({})", message),
+                    }
+                );
+            }
+        }
+    }
+
+    problems
+}
+
+/// Print the result of a Trello credentials check.
+pub fn print_trello_check(check: &TrelloTokenCheck) {
+    println!(
+        "[self-assessment] ✅ The Trello credentials belong to {}.",
+        check.full_name
+    );
+    match check.expires_at {
+        Some(expires_at) => println!(
+            "[self-assessment] 📅 The Trello server token expires on {}.",
+            expires_at.with_timezone(&Local).format("%Y-%m-%d %H:%M")
+        ),
+        None => println!("[self-assessment] 📅 The Trello server token doesn't expire."),
+    }
+}
+
+/// Check the configuration, credentials and cache, and explain how to fix any problems.
+pub async fn doctor() -> anyhow::Result<()> {
+    let mut problems = 0;
+
+    let path = store_path();
+    match std::fs::metadata(&path) {
+        Ok(metadata) => {
+            println!("[self-assessment] ✅ Settings are read from {}.", path);
+            #[cfg(unix)]
+            if std::os::unix::fs::PermissionsExt::mode(&metadata.permissions()) & 0o077 != 0 {
+                problems += 1;
+                println!(
+                    "[self-assessment] ⚠️ {} can be read by other users. Run `self-assessment migrate-credentials` to fix this.",
+                    path
+                );
+            }
+            #[cfg(not(unix))]
+            let _ = metadata;
+        }
+        Err(_) => println!("[self-assessment] ℹ️ {} doesn't exist yet.", path),
+    }
+
+    match credential_store() {
+        Ok(store) => println!(
+            "[self-assessment] ✅ Credentials are saved to {}.",
+            store.name()
+        ),
+        Err(err) => {
+            problems += 1;
+            println!("[self-assessment] ❌ {}", describe_error(&err));
+        }
+    }
+
+    match get_auth_token(AuthType::GitHubAuthToken) {
+        Ok(Some(token)) => {
+            println!(
+                "[self-assessment] 🔑 Found the GitHub token in {}.",
+                token.source
+            );
+            let check = match GithubHost::from_settings() {
+                Ok(host) => {
+                    if !host.is_github_com() {
+                        println!(
+                            "[self-assessment] 🏢 Using GitHub Enterprise Server at {}.",
+                            host.web_url
+                        );
+                    }
+                    verify_github_token(&host, &token.value, &github_orgs(vec![])).await
+                }
+                Err(err) => Err(err),
+            };
+            match check {
+                Ok(check) => problems += print_github_check(&check),
+                Err(err) => {
+                    problems += 1;
+                    println!("[self-assessment] ❌ {}", describe_error(&err));
+                }
+            }
+        }
+        Ok(None) => {
+            problems += 1;
+            println!(
+                "[self-assessment] ❌ No GitHub token found. Run `self-assessment auth <TOKEN>`."
+            );
+        }
+        Err(err) => {
+            problems += 1;
+            println!("[self-assessment] ❌ {}", describe_error(&err));
+        }
+    }
+
+    match (
+        get_auth_token(AuthType::TrelloApiKey),
+        get_auth_token(AuthType::TrelloServerToken),
+    ) {
+        (Err(err), _) | (_, Err(err)) => {
+            problems += 1;
+            println!("[self-assessment] ❌ {}", describe_error(&err));
+        }
+        (Ok(Some(key)), Ok(Some(token))) => {
+            println!(
+                "[self-assessment] 🔑 Found the Trello API key in {} and the server token in {}.",
+                key.source, token.source
+            );
+            match verify_trello_credentials(&key.value, &token.value).await {
+                Ok(check) => print_trello_check(&check),
+                Err(err) => {
+                    problems += 1;
+                    println!("[self-assessment] ❌ {}", describe_error(&err));
+                }
+            }
+        }
+        (Ok(None), Ok(None)) => println!(
+            "[self-assessment] ℹ️ Trello isn't set up, so reports won't include Trello cards."
+        ),
+        (_, _) => {
+            problems += 1;
+            println!("[self-assessment] ❌ Only one of the Trello API key and server token is set. Run `self-assessment trello-auth <API_KEY> <TOKEN>`.");
+        }
+    }
+
+    match Cache::open() {
+        Ok(cache) => println!(
+            "[self-assessment] ✅ The cache is at {}.",
+            cache.location().display()
+        ),
+        Err(err) => {
+            problems += 1;
+            println!(
+                "[self-assessment] ⚠️ The cache can't be used: {}",
+                describe_error(&err)
+            );
+        }
+    }
+
+    if problems > 0 {
+        bail!(
+            "Found {} problem{}",
+            problems,
+            if problems == 1 { "" } else { "s" }
+        );
+    }
+    println!("[self-assessment] 🎉 Everything looks good.");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sso_required_with_the_authorisation_url() {
+        assert_eq!(
+            parse_sso_header(
+                "required; url=https://github.com/orgs/guardian/sso?authorization_request=A1b2"
+            ),
+            Some(SsoHeader::Required {
+                url: Some(
+                    "https://github.com/orgs/guardian/sso?authorization_request=A1b2".to_string()
+                )
+            })
+        );
+        assert_eq!(
+            parse_sso_header("required"),
+            Some(SsoHeader::Required { url: None })
+        );
+    }
+
+    #[test]
+    fn parses_the_organisations_left_out_of_partial_results() {
+        assert_eq!(
+            parse_sso_header("partial-results; organizations=21955855,20582480"),
+            Some(SsoHeader::PartialResults(vec![21955855, 20582480]))
+        );
+        assert_eq!(
+            parse_sso_header("partial-results; organizations="),
+            Some(SsoHeader::PartialResults(vec![]))
+        );
+    }
+
+    #[test]
+    fn ignores_other_sso_headers() {
+        assert_eq!(parse_sso_header(""), None);
+        assert_eq!(parse_sso_header("something-new; x=1"), None);
+    }
+}
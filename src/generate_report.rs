@@ -1,22 +1,48 @@
 use std::{
-    fs::File,
-    os::unix::process::CommandExt,
-    process::{self, Command},
+    fs::{self, File},
+    path::{Path, PathBuf},
+    process::Command,
 };
 
-use chrono::Datelike;
-use handlebars::{to_json, Context, Handlebars, Helper, Output, RenderContext, RenderError};
-use octocrab::Octocrab;
-use serde_json::Map;
+use anyhow::{anyhow, Context as _};
+use chrono::{DateTime, Datelike, Local, Utc};
+use clap::ArgEnum;
+use handlebars::{no_escape, Context, Handlebars, Helper, Output, RenderContext, RenderError};
 
-use crate::cli::AuthType;
-use crate::credentials::get_auth_token;
-use crate::github::{format_prs, prepare_parameters, search_pull_requests};
-use crate::models::{BoardAndCards, GuardianPullRequests, TemplatePr, TrelloUser};
+use crate::cache::Cache;
+use crate::charts::draw_charts;
+use crate::cli::{AuthType, GithubApi, GroupBy, ReportFormat, ReportOptions, TrelloDateField};
+use crate::config::{get_setting, github_orgs};
+use crate::credentials::{get_auth_token, read_secret_from_stdin, Credential};
+use crate::dates::{resolve_date_range, DateRange};
+use crate::github::{
+    cached_search_results, format_prs, merged_at, repo_full_name, search_github, GithubBackend,
+    RestBackend,
+};
+use crate::github_client::GithubClient;
+use crate::github_graphql::GraphqlBackend;
+use crate::github_host::GithubHost;
+use crate::grouping::group_items;
+use crate::metrics::{fetch_pr_stats, pr_metrics};
+use crate::models::{
+    CsvPrRow, CsvTrelloCardRow, GithubSearchResponseItem, JsonDateRange, JsonGithubUser, JsonGroup,
+    JsonGroups, JsonReport, JsonTrello, JsonTrelloBoard, ReportCredential, ReportData,
+    SearchCategory, SearchResults, TemplateCharts, TemplateContext, TemplateGroup, TrelloUser,
+    JSON_SCHEMA_VERSION,
+};
+use crate::reviews::fetch_review_activity;
+use crate::summary::summarise;
 use crate::trello::{
-    format_trello_cards, search_trello, search_trello_user, trello_board_and_cards_len,
+    cached_trello_cards, format_trello_cards, search_trello, search_trello_user,
+    trello_board_and_cards_len,
 };
 
+/// Cache keys of the users the cached data belongs to.
+const CACHED_GITHUB_USER: &str = "github_user";
+const CACHED_TRELLO_USER: &str = "trello_user";
+/// Cache key of the API URL the cached GitHub data was fetched from.
+const CACHED_GITHUB_API_URL: &str = "github_api_url";
+
 fn array_length_helper(
     h: &Helper,
     _: &Handlebars,
@@ -38,137 +64,726 @@ fn array_length_helper(
     Ok(())
 }
 
-pub fn generate_html_file(
-    user: octocrab::models::Author,
-    prs: &[TemplatePr],
-    reviews: &[TemplatePr],
-    trello_user: &Option<TrelloUser>,
-    trello_board_with_cards: &Option<Vec<BoardAndCards>>,
-    from: &str,
-    to: &str,
-) -> anyhow::Result<String> {
+/// Render the report with a Handlebars template and write it to disk.
+/// Without a custom template, the built-in one for `format` is used.
+fn generate_template_file(
+    report: &ReportData,
+    format: ReportFormat,
+    output: &Path,
+    template: Option<&Path>,
+    group_by: Option<GroupBy>,
+) -> anyhow::Result<()> {
+    // Write templates into binary
+    static HTML_TEMPLATE: &str = include_str!("./template/template.hbs");
+    static MARKDOWN_TEMPLATE: &str = include_str!("./template/template.md.hbs");
+
     let mut reg = Handlebars::new();
     reg.register_helper("array_length", Box::new(array_length_helper));
-    reg.register_template_string("template", TEMPLATE).unwrap();
+    if format == ReportFormat::Markdown {
+        // Markdown isn't HTML, so values are written out as they are
+        reg.register_escape_fn(no_escape);
+    }
+    match (template, format) {
+        (Some(template), _) => register_custom_template(&mut reg, template)?,
+        (None, ReportFormat::Markdown) => {
+            reg.register_template_string("template", MARKDOWN_TEMPLATE)?
+        }
+        (None, _) => {
+            // Its inline partials are already indented to fit where they're used
+            reg.set_prevent_indent(true);
+            reg.register_template_string("template", HTML_TEMPLATE)?
+        }
+    }
 
-    // Write HTML template into binary
-    static TEMPLATE: &str = include_str!("./template/template.hbs");
+    let prs = format_prs(&report.authored.items, format);
+    let reviews = format_prs(&report.reviewed.items, format);
+    let issues_opened = format_prs(&report.issues_opened.items, format);
+    let issues_commented = format_prs(&report.issues_commented.items, format);
+    let issues_assigned = format_prs(&report.issues_assigned.items, format);
+    let trello_boards = report
+        .trello_cards
+        .as_ref()
+        .map(|cards| format_trello_cards(cards, format));
 
-    let from = if from == "*" {
-        "From the day you joined the Guardian".to_string()
-    } else {
-        format!("From {}", from)
+    let groups = |items: &[GithubSearchResponseItem]| match group_by {
+        Some(group_by) => group_items(items, group_by)
+            .into_iter()
+            .map(|group| TemplateGroup {
+                name: Some(group.name),
+                count: group.items.len(),
+                items: format_prs(group.items, format),
+            })
+            .collect(),
+        None => vec![TemplateGroup {
+            name: None,
+            count: items.len(),
+            items: format_prs(items, format),
+        }],
     };
-    let to = if to == "*" {
-        "until today".to_string()
-    } else {
-        format!("to {}", to)
-    };
-
-    let mut data = Map::new();
-
-    // GitHub Template
-    data.insert("github_user".to_string(), to_json(user.login));
-    data.insert("start_date".to_string(), to_json(from));
-    data.insert("end_date".to_string(), to_json(to));
-    data.insert("prs".to_string(), to_json(prs));
-    data.insert("reviews".to_string(), to_json(reviews));
-    data.insert("prs_len".to_string(), to_json(prs.len()));
-    data.insert("reviews_len".to_string(), to_json(reviews.len()));
-    data.insert(
-        "trello_boards".to_string(),
-        to_json(trello_board_with_cards),
-    );
 
-    // Trello Template
-    let mut board_len: usize = 0;
-    let mut c_len: usize = 0;
+    let start_date = match report.date_range.from {
+        Some(from) => format!("From {}", from),
+        None => "From the day you joined the Guardian".to_string(),
+    };
+    let end_date = match report.date_range.to {
+        Some(to) => format!("to {}", to),
+        None => "until today".to_string(),
+    };
+
+    let (_, cards_len) = trello_boards
+        .as_deref()
+        .map(trello_board_and_cards_len)
+        .unwrap_or_default();
+
+    let summary = summarise(report);
+    let data = TemplateContext {
+        github_user: &report.github_user.login,
+        start_date,
+        end_date,
+        prs_len: prs.len(),
+        reviews_len: reviews.len(),
+        group_by: group_by.map(|g| g.name()),
+        prs_groups: groups(&report.authored.items),
+        reviews_groups: groups(&report.reviewed.items),
+        issues_opened_groups: groups(&report.issues_opened.items),
+        issues_commented_groups: groups(&report.issues_commented.items),
+        issues_assigned_groups: groups(&report.issues_assigned.items),
+        pr_metrics: pr_metrics(&report.authored.items),
+        charts: match format {
+            ReportFormat::Html => draw_charts(report, &summary),
+            _ => TemplateCharts::default(),
+        },
+        summary,
+        prs,
+        reviews,
+        issues_opened_len: issues_opened.len(),
+        issues_commented_len: issues_commented.len(),
+        issues_assigned_len: issues_assigned.len(),
+        issues_opened,
+        issues_commented,
+        issues_assigned,
+        partial_data: report.partial_data(),
+        display_trello: report.trello_user.is_some() && trello_boards.is_some(),
+        trello_boards,
+        cards_len,
+        user: report.trello_user.as_ref(),
+        credentials: &report.credentials,
+    };
+
+    let mut output_file = File::create(output)?;
+    reg.render_to_write("template", &data, &mut output_file)
+        .context("Failed to render the report template")?;
+
+    print_summary(report);
+
+    Ok(())
+}
+
+/// Register a user-supplied template, along with every other `.hbs` file in its directory
+/// as a partial named after the file, e.g. `header.hbs` can be included with `{{> header}}`.
+fn register_custom_template(reg: &mut Handlebars, template: &Path) -> anyhow::Result<()> {
+    let contents = fs::read_to_string(template)
+        .with_context(|| format!("Couldn't read the template {}", template.display()))?;
+    reg.register_template_string("template", contents)
+        .with_context(|| format!("Couldn't compile the template {}", template.display()))?;
+
+    let template_dir = match template.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    for entry in fs::read_dir(template_dir)? {
+        let path = entry?.path();
+        if path == template || path.extension().and_then(|e| e.to_str()) != Some("hbs") {
+            continue;
+        }
+        let name = match path.file_stem().and_then(|s| s.to_str()) {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+        let partial = fs::read_to_string(&path)
+            .with_context(|| format!("Couldn't read the partial {}", path.display()))?;
+        reg.register_partial(&name, partial)
+            .with_context(|| format!("Couldn't compile the partial {}", path.display()))?;
+    }
+
+    Ok(())
+}
+
+/// Generate an HTML report.
+pub fn generate_html_file(
+    report: &ReportData,
+    output: &Path,
+    template: Option<&Path>,
+    group_by: Option<GroupBy>,
+) -> anyhow::Result<()> {
+    generate_template_file(report, ReportFormat::Html, output, template, group_by)
+}
+
+/// Generate a Markdown report, suitable for pasting into documents and forms.
+/// PR descriptions are kept as Markdown rather than converted to HTML.
+pub fn generate_markdown_file(
+    report: &ReportData,
+    output: &Path,
+    template: Option<&Path>,
+    group_by: Option<GroupBy>,
+) -> anyhow::Result<()> {
+    generate_template_file(report, ReportFormat::Markdown, output, template, group_by)
+}
+
+/// Generate a JSON export of the collected data, following the versioned [`JsonReport`] schema.
+pub fn generate_json_file(
+    report: &ReportData,
+    output: &Path,
+    group_by: Option<GroupBy>,
+) -> anyhow::Result<()> {
+    let trello = match (&report.trello_user, &report.trello_cards) {
+        (Some(user), Some(cards)) => {
+            let mut boards: Vec<JsonTrelloBoard> = cards
+                .iter()
+                .map(|(name, cards)| JsonTrelloBoard { name, cards })
+                .collect();
+            boards.sort_by_key(|b| b.name);
+            Some(JsonTrello { user, boards })
+        }
+        _ => None,
+    };
+
+    let groups = group_by.map(|group_by| {
+        let groups = |items: &[GithubSearchResponseItem]| {
+            group_items(items, group_by)
+                .into_iter()
+                .map(|group| JsonGroup {
+                    name: group.name,
+                    count: group.items.len(),
+                    html_urls: group.items.iter().map(|i| i.html_url.to_string()).collect(),
+                })
+                .collect()
+        };
+        JsonGroups {
+            by: group_by.name(),
+            authored: groups(&report.authored.items),
+            reviewed: groups(&report.reviewed.items),
+            issues_opened: groups(&report.issues_opened.items),
+            issues_commented: groups(&report.issues_commented.items),
+            issues_assigned: groups(&report.issues_assigned.items),
+        }
+    });
+
+    let json_report = JsonReport {
+        schema_version: JSON_SCHEMA_VERSION,
+        generated_at: chrono::Utc::now(),
+        github_user: JsonGithubUser {
+            login: report.github_user.login.to_string(),
+            id: report.github_user.id.0,
+            html_url: report.github_user.html_url.to_string(),
+            avatar_url: report.github_user.avatar_url.to_string(),
+        },
+        date_range: JsonDateRange {
+            from: report.date_range.from.map(|d| d.to_string()),
+            to: report.date_range.to.map(|d| d.to_string()),
+        },
+        partial_data: report.partial_data(),
+        authored: &report.authored.items,
+        reviewed: &report.reviewed.items,
+        issues_opened: &report.issues_opened.items,
+        issues_commented: &report.issues_commented.items,
+        issues_assigned: &report.issues_assigned.items,
+        summary: summarise(report),
+        groups,
+        trello,
+        credentials: &report.credentials,
+    };
+
+    let output_file = File::create(output)?;
+    serde_json::to_writer_pretty(output_file, &json_report)?;
+
+    print_summary(report);
+
+    Ok(())
+}
+
+/// Generate a CSV file with one row per PR or issue, with its category in the `kind` column.
+/// Trello cards, if any, are written to a second CSV file alongside it.
+pub fn generate_csv_file(report: &ReportData, output: &Path) -> anyhow::Result<()> {
+    let date = |d: DateTime<Utc>| d.format("%Y-%m-%d").to_string();
+
+    let mut writer = csv::Writer::from_path(output)?;
+
+    let prs = [
+        (SearchCategory::AuthoredByMe, &report.authored),
+        (SearchCategory::ReviewedByMe, &report.reviewed),
+        (SearchCategory::IssuesOpenedByMe, &report.issues_opened),
+        (
+            SearchCategory::IssuesCommentedOnByMe,
+            &report.issues_commented,
+        ),
+        (SearchCategory::IssuesAssignedToMe, &report.issues_assigned),
+    ]
+    .into_iter()
+    .flat_map(|(category, results)| {
+        results
+            .items
+            .iter()
+            .map(move |pr| (category.cache_key(), pr))
+    });
+
+    for (kind, pr) in prs {
+        writer.serialize(CsvPrRow {
+            kind,
+            repo: repo_full_name(&pr.repository_url)
+                .unwrap_or_else(|| pr.repository_url.to_string()),
+            number: pr.number,
+            title: &pr.title,
+            state: &pr.state,
+            merged: merged_at(pr).is_some(),
+            created_at: date(pr.created_at),
+            closed_at: pr.closed_at.map(date),
+            merged_at: merged_at(pr).map(date),
+            comments: pr.comments,
+            labels: pr
+                .labels
+                .iter()
+                .map(|l| l.name.as_str())
+                .collect::<Vec<&str>>()
+                .join("; "),
+            url: &pr.html_url,
+            additions: pr.pr_stats.as_ref().map(|s| s.additions),
+            deletions: pr.pr_stats.as_ref().map(|s| s.deletions),
+            changed_files: pr.pr_stats.as_ref().map(|s| s.changed_files),
+            commits: pr.pr_stats.as_ref().map(|s| s.commits),
+        })?;
+    }
+    writer.flush()?;
+
+    if let Some(cards) = &report.trello_cards {
+        let trello_file_name = output.with_file_name(format!(
+            "{}-trello.csv",
+            output.file_stem().unwrap_or_default().to_string_lossy()
+        ));
+        let mut writer = csv::Writer::from_path(&trello_file_name)?;
+
+        let mut boards: Vec<&String> = cards.keys().collect();
+        boards.sort();
+        for board in boards {
+            for card in &cards[board] {
+                writer.serialize(CsvTrelloCardRow {
+                    board,
+                    card: &card.name,
+                    labels: card
+                        .labels
+                        .iter()
+                        .map(|l| l.name.as_str())
+                        .collect::<Vec<&str>>()
+                        .join("; "),
+                    last_activity: date(card.date_last_activity),
+                    url: &card.url,
+                })?;
+            }
+        }
+        writer.flush()?;
 
-    if let (Some(u), Some(b)) = (trello_user, trello_board_with_cards) {
-        let (b_len, cards_len) = trello_board_and_cards_len(b);
-        c_len = cards_len;
-        data.insert("cards_len".to_string(), to_json(cards_len));
-        data.insert("user".to_string(), to_json(u));
-        data.insert("display_trello".to_string(), to_json(true));
-        board_len = b_len;
+        println!(
+            "[self-assessment] 🃏 Trello cards written to {}",
+            trello_file_name.display()
+        );
     }
 
+    print_summary(report);
+
+    Ok(())
+}
+
+/// Today's report file name, e.g. `2024-05-13-self-assessment.html`.
+fn output_file_name(extension: &str) -> String {
     let now = chrono::Utc::now();
-    let output_file_name = format!(
-        "{}-{:02}-{:02}-self-assessment.html",
+    format!(
+        "{}-{:02}-{:02}-self-assessment.{}",
         now.year_ce().1,
         now.month(),
-        now.day()
-    );
+        now.day(),
+        extension
+    )
+}
 
-    let mut output_file = File::create(&output_file_name)?;
-    reg.render_to_write("template", &data, &mut output_file)?;
+/// Work out where to write the report from the `--output` flag.
+/// Without the flag, or when it points to a directory, the report gets its default file name.
+fn output_path(output: Option<PathBuf>, format: ReportFormat) -> PathBuf {
+    match output {
+        Some(path) if path.is_dir() => path.join(output_file_name(format.extension())),
+        Some(path) => path,
+        None => PathBuf::from(output_file_name(format.extension())),
+    }
+}
+
+/// Open the report with the platform's default application.
+/// Failing to open it isn't an error, since the report has already been written.
+fn open_report(path: &Path) {
+    let mut open = if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else if cfg!(target_os = "windows") {
+        let mut start = Command::new("cmd");
+        // The empty argument is the window title, which `start` expects before the path
+        start.args(["/C", "start", ""]);
+        start
+    } else {
+        Command::new("xdg-open")
+    };
+    open.arg(path);
 
+    match open.status() {
+        Ok(status) if status.success() => {}
+        Ok(status) => eprintln!(
+            "[self-assessment] ⚠️  Couldn't open the report ({}). You can find it at {}",
+            status,
+            path.display()
+        ),
+        Err(err) => eprintln!(
+            "[self-assessment] ⚠️  Couldn't open the report ({}). You can find it at {}",
+            err,
+            path.display()
+        ),
+    }
+}
+
+fn print_summary(report: &ReportData) {
+    let prs_len = report.authored.items.len();
+    let reviews_len = report.reviewed.items.len();
     println!(
         "[self-assessment] ✨ Generated a report containing {} PRs ({} authored, {} reviewed)",
-        prs.len() + reviews.len(),
-        prs.len(),
-        reviews.len()
+        prs_len + reviews_len,
+        prs_len,
+        reviews_len
     );
-    if trello_board_with_cards.is_some() {
+    let issues_len = report.issues_opened.items.len()
+        + report.issues_commented.items.len()
+        + report.issues_assigned.items.len();
+    if issues_len > 0 {
+        println!(
+            "[self-assessment] ✨ ...and {} issues ({} opened, {} commented on, {} assigned)",
+            issues_len,
+            report.issues_opened.items.len(),
+            report.issues_commented.items.len(),
+            report.issues_assigned.items.len()
+        );
+    }
+    if let Some(cards) = &report.trello_cards {
         println!(
             "[self-assessment] ✨ ...including {} cards in {} Trello boards",
-            c_len, board_len
+            cards.values().map(|c| c.len()).sum::<usize>(),
+            cards.len()
+        )
+    }
+    if report.partial_data() {
+        println!(
+            "[self-assessment] ⚠️  GitHub returned partial data, so some PRs or issues may be missing from the report. Try again later or narrow the date range."
+        );
+    }
+}
+
+pub async fn generate_report(options: ReportOptions) -> anyhow::Result<()> {
+    let ReportOptions {
+        from,
+        to,
+        period,
+        since,
+        skip_trello,
+        trello_date,
+        orgs,
+        format,
+        output,
+        no_open,
+        template,
+        token_stdin,
+        offline,
+        github_api,
+        group_by,
+    } = options;
+
+    // Fall back to the default date range of the profile when no dates are given
+    let (period, since) = match (from, to, &period, &since) {
+        (None, None, None, None) => (get_setting("PERIOD"), get_setting("SINCE")),
+        _ => (period, since),
+    };
+
+    let format = match (format, get_setting("FORMAT")) {
+        (Some(format), _) => format,
+        (None, Some(setting)) => ReportFormat::from_str(&setting, true).map_err(|_| {
+            anyhow!(
+                "Unknown FORMAT setting \"{}\", expected html, markdown, json or csv",
+                setting
+            )
+        })?,
+        (None, None) => ReportFormat::Html,
+    };
+
+    let github_api = match (github_api, get_setting("GITHUB_API")) {
+        (Some(github_api), _) => github_api,
+        (None, Some(setting)) => GithubApi::from_str(&setting, true).map_err(|_| {
+            anyhow!(
+                "Unknown GITHUB_API setting \"{}\", expected rest or graphql",
+                setting
+            )
+        })?,
+        (None, None) => GithubApi::Rest,
+    };
+
+    let group_by = match (group_by, get_setting("GROUP_BY")) {
+        (Some(group_by), _) => Some(group_by),
+        (None, Some(setting)) => Some(GroupBy::from_str(&setting, true).map_err(|_| {
+            anyhow!(
+                "Unknown GROUP_BY setting \"{}\", expected repo, month, quarter or label",
+                setting
+            )
+        })?),
+        (None, None) => None,
+    };
+    if group_by.is_some() && format == ReportFormat::Csv {
+        println!(
+            "[self-assessment] ⏩ CSV reports have one row per PR or issue, ignoring the grouping."
+        );
+    }
+
+    let date_range = resolve_date_range(
+        from,
+        to,
+        period.as_deref(),
+        since.as_deref(),
+        Local::now().date_naive(),
+    )?;
+
+    let template = template.or_else(|| {
+        get_setting("TEMPLATE").map(|t| PathBuf::from(shellexpand::tilde(&t).to_string()))
+    });
+    if template.is_some() && matches!(format, ReportFormat::Json | ReportFormat::Csv) {
+        println!("[self-assessment] ⏩ Templates only apply to HTML and Markdown reports, ignoring the template.");
+    }
+
+    let orgs = github_orgs(orgs);
+
+    let report = if offline {
+        let cache = Cache::open()?;
+        collect_offline(&cache, &orgs, date_range, skip_trello, trello_date)?
+    } else {
+        // The cache only saves time, so carry on without it if it can't be opened
+        let cache = match Cache::open() {
+            Ok(cache) => Some(cache),
+            Err(err) => {
+                eprintln!("[self-assessment] ⚠️ Not using the cache: {:#}", err);
+                None
+            }
+        };
+        collect_online(
+            cache.as_ref(),
+            &orgs,
+            date_range,
+            skip_trello,
+            trello_date,
+            token_stdin,
+            github_api,
         )
+        .await?
+    };
+
+    let output = output_path(output, format);
+    match format {
+        ReportFormat::Html => generate_html_file(&report, &output, template.as_deref(), group_by)?,
+        ReportFormat::Markdown => {
+            generate_markdown_file(&report, &output, template.as_deref(), group_by)?
+        }
+        ReportFormat::Json => generate_json_file(&report, &output, group_by)?,
+        ReportFormat::Csv => generate_csv_file(&report, &output)?,
     }
+    println!(
+        "[self-assessment] 📄 Report written to {}",
+        output.display()
+    );
 
-    Ok(output_file_name)
+    if !no_open {
+        open_report(&output);
+    }
+
+    Ok(())
 }
 
-pub async fn generate_report(from: String, to: String, skip_trello: bool) -> anyhow::Result<()> {
-    let github_auth_token = get_auth_token(AuthType::GitHubAuthToken);
-    let trello_key = get_auth_token(AuthType::TrelloApiKey);
-    let trello_token = get_auth_token(AuthType::TrelloServerToken);
+/// Fetch the report data from GitHub and Trello, keeping the cache up to date along the way.
+async fn collect_online(
+    cache: Option<&Cache>,
+    orgs: &[String],
+    date_range: DateRange,
+    skip_trello: bool,
+    trello_date: TrelloDateField,
+    token_stdin: bool,
+    github_api: GithubApi,
+) -> anyhow::Result<ReportData> {
+    let github_auth_token = match token_stdin {
+        true => Some(read_secret_from_stdin(AuthType::GitHubAuthToken)?),
+        false => get_auth_token(AuthType::GitHubAuthToken)?,
+    };
+    let (trello_key, trello_token) = match skip_trello {
+        true => (None, None),
+        false => (
+            get_auth_token(AuthType::TrelloApiKey)?,
+            get_auth_token(AuthType::TrelloServerToken)?,
+        ),
+    };
 
     // The GitHub auth token is the minimum config parameter needed to run the CLI,
     // so exit if it's not found
-    if github_auth_token.is_none() {
+    let Some(github_auth_token) = github_auth_token else {
         eprintln!("[self-assessment] ❌ Unable to fetch the GitHub authentication token.");
-        eprintln!("[self-assessment] ❌ Run `self-assessment auth <TOKEN>`");
+        eprintln!("[self-assessment] ❌ Run `self-assessment auth <TOKEN>`, or set the GITHUB_TOKEN environment variable");
         std::process::exit(1);
+    };
+
+    let mut credentials = vec![];
+    for (credential, found) in [
+        (AuthType::GitHubAuthToken, Some(&github_auth_token)),
+        (AuthType::TrelloApiKey, trello_key.as_ref()),
+        (AuthType::TrelloServerToken, trello_token.as_ref()),
+    ] {
+        if let Some(found) = found {
+            println!(
+                "[self-assessment] 🔑 Using the {} from {}",
+                credential.description(),
+                found.source
+            );
+            credentials.push(ReportCredential {
+                credential: credential.description(),
+                source: found.source.to_string(),
+            });
+        }
     }
 
-    let octocrab = Octocrab::builder()
-        .personal_token(github_auth_token.unwrap())
-        .build()?;
+    let host = GithubHost::from_settings()?;
+    if !host.is_github_com() {
+        println!(
+            "[self-assessment] 🏢 Using GitHub Enterprise Server at {}",
+            host.web_url
+        );
+    }
+    if let Some(cache) = cache {
+        if cache.created() {
+            cache.set_meta(CACHED_GITHUB_API_URL, &host.api_url)?;
+        }
+        match cache.meta::<String>(CACHED_GITHUB_API_URL)? {
+            Some(cached_api_url) if cached_api_url == host.api_url => {}
+            cached_api_url => {
+                match cached_api_url {
+                    Some(cached_api_url) => println!(
+                        "[self-assessment] 🧹 The GitHub API URL changed from {}, so the cached GitHub data is fetched again",
+                        cached_api_url
+                    ),
+                    None => println!(
+                        "[self-assessment] 🧹 The cache doesn't say which GitHub API URL its data came from, so the cached GitHub data is fetched again"
+                    ),
+                }
+                cache.clear_github()?;
+                cache.set_meta(CACHED_GITHUB_API_URL, &host.api_url)?;
+            }
+        }
+    }
 
-    let github_user = octocrab.current().user().await?;
-    let mut github_params = prepare_parameters();
+    let github_client = GithubClient::new(host, &github_auth_token.value)?;
+
+    let github_user = github_client.octocrab.current().user().await?;
+    if let Some(cache) = cache {
+        let cached_user = cache.meta::<octocrab::models::Author>(CACHED_GITHUB_USER)?;
+        if let Some(cached_user) = cached_user.filter(|u| u.id != github_user.id) {
+            println!(
+                "[self-assessment] 🧹 The GitHub token now belongs to {} instead of {}, so the cached GitHub data is fetched again",
+                github_user.login, cached_user.login
+            );
+            cache.clear_github()?;
+        }
+        cache.set_meta(CACHED_GITHUB_USER, &github_user)?;
+    }
+
+    let backend: Box<dyn GithubBackend> = match github_api {
+        GithubApi::Rest => Box::new(RestBackend {
+            client: &github_client,
+        }),
+        GithubApi::Graphql => Box::new(GraphqlBackend {
+            client: &github_client,
+            login: github_user.login.to_string(),
+        }),
+    };
 
     // Query the Github API with custom queries
-    let authored_prs = search_pull_requests(
-        &octocrab,
-        GuardianPullRequests::AuthoredByMe,
-        &mut github_params,
-        &from,
-        &to,
+    let mut authored_prs = search_github(
+        backend.as_ref(),
+        SearchCategory::AuthoredByMe,
+        orgs,
+        &date_range,
+        cache,
     )
     .await?;
 
-    let reviewed_prs = search_pull_requests(
-        &octocrab,
-        GuardianPullRequests::ReviewedByMe,
-        &mut github_params,
-        &from,
-        &to,
+    let mut reviewed_prs = search_github(
+        backend.as_ref(),
+        SearchCategory::ReviewedByMe,
+        orgs,
+        &date_range,
+        cache,
     )
     .await?;
 
-    let formatted_prs = format_prs(&authored_prs);
-    let formatted_reviews = format_prs(&reviewed_prs);
+    let issues_opened = search_github(
+        backend.as_ref(),
+        SearchCategory::IssuesOpenedByMe,
+        orgs,
+        &date_range,
+        cache,
+    )
+    .await?;
+
+    let issues_commented = search_github(
+        backend.as_ref(),
+        SearchCategory::IssuesCommentedOnByMe,
+        orgs,
+        &date_range,
+        cache,
+    )
+    .await?;
+
+    let issues_assigned = search_github(
+        backend.as_ref(),
+        SearchCategory::IssuesAssignedToMe,
+        orgs,
+        &date_range,
+        cache,
+    )
+    .await?;
+
+    fetch_pr_stats(&github_client, &mut authored_prs.items, cache).await?;
+    fetch_review_activity(
+        &github_client,
+        &github_user.login,
+        &mut reviewed_prs.items,
+        cache,
+    )
+    .await?;
+
+    println!(
+        "[self-assessment] 📡 Made {} requests to the GitHub API",
+        github_client.request_count()
+    );
 
     // Trello integration
     let mut trello_user = None;
-    let mut formatted_trello_cards = None;
+    let mut trello_cards = None;
 
     if !skip_trello {
         match (trello_key, trello_token) {
-            (Some(trello_key), Some(trello_token)) => {
+            (
+                Some(Credential {
+                    value: trello_key, ..
+                }),
+                Some(Credential {
+                    value: trello_token,
+                    ..
+                }),
+            ) => {
                 let trello_client = reqwest::ClientBuilder::new().build()?;
                 let maybe_user =
                     search_trello_user(&trello_client, trello_key.clone(), trello_token.clone())
@@ -176,18 +791,22 @@ pub async fn generate_report(from: String, to: String, skip_trello: bool) -> any
 
                 match maybe_user {
                     Ok(user) => {
-                        let trello_cards = search_trello(
+                        if let Some(cache) = cache {
+                            cache.set_meta(CACHED_TRELLO_USER, &user)?;
+                        }
+                        let cards = search_trello(
                             &trello_client,
                             trello_key,
                             trello_token,
                             &user,
-                            &from,
-                            &to,
+                            &date_range,
+                            trello_date,
+                            cache,
                         )
                         .await?;
 
                         trello_user = Option::from(user);
-                        formatted_trello_cards = Option::from(format_trello_cards(&trello_cards));
+                        trello_cards = Option::from(cards);
                     }
                     Err(err) => {
                         eprintln!("[self-assessment] 🚫 Trello error: \"{}\"", err);
@@ -201,32 +820,64 @@ pub async fn generate_report(from: String, to: String, skip_trello: bool) -> any
         }
     }
 
-    // Generate HTML file
-    let html_file = generate_html_file(
+    Ok(ReportData {
         github_user,
-        &formatted_prs,
-        &formatted_reviews,
-        &trello_user,
-        &formatted_trello_cards,
-        &from,
-        &to,
+        authored: authored_prs,
+        reviewed: reviewed_prs,
+        issues_opened,
+        issues_commented,
+        issues_assigned,
+        trello_user,
+        trello_cards,
+        date_range,
+        credentials,
+    })
+}
+
+/// Load the report data from the cache, without going online.
+fn collect_offline(
+    cache: &Cache,
+    orgs: &[String],
+    date_range: DateRange,
+    skip_trello: bool,
+    trello_date: TrelloDateField,
+) -> anyhow::Result<ReportData> {
+    println!(
+        "[self-assessment] 📦 Generating the report offline from {}",
+        Cache::path()?.display()
     );
 
-    // Automatically open the file if the operation succeeds
-    match html_file {
-        Ok(file_name) => {
-            let mut open = Command::new("open");
-            open.arg(file_name);
-            // Exec the opening of the file.
-            // If all goes well, this will never return.
-            // If it does return, it will always retun an error.
-            let err = open.exec();
-            eprintln!("Error opening file: {}", err);
-            process::exit(1);
-        }
-        Err(err) => {
-            eprintln!("{}", err);
-            process::exit(1);
-        }
-    }
+    let github_user = cache.meta(CACHED_GITHUB_USER)?.ok_or_else(|| {
+        anyhow!("Nothing has been cached yet. Generate a report online first, then use --offline.")
+    })?;
+
+    let cached = |category| -> anyhow::Result<SearchResults> {
+        cached_search_results(cache, category, orgs, &date_range)
+    };
+    let authored_prs = cached(SearchCategory::AuthoredByMe)?;
+    let reviewed_prs = cached(SearchCategory::ReviewedByMe)?;
+    let issues_opened = cached(SearchCategory::IssuesOpenedByMe)?;
+    let issues_commented = cached(SearchCategory::IssuesCommentedOnByMe)?;
+    let issues_assigned = cached(SearchCategory::IssuesAssignedToMe)?;
+
+    let (trello_user, trello_cards) = match cache.meta::<TrelloUser>(CACHED_TRELLO_USER)? {
+        Some(user) if !skip_trello => (
+            Some(user),
+            Some(cached_trello_cards(cache, &date_range, trello_date)?),
+        ),
+        _ => (None, None),
+    };
+
+    Ok(ReportData {
+        github_user,
+        authored: authored_prs,
+        reviewed: reviewed_prs,
+        issues_opened,
+        issues_commented,
+        issues_assigned,
+        trello_user,
+        trello_cards,
+        date_range,
+        credentials: vec![],
+    })
 }
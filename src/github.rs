@@ -1,15 +1,38 @@
-use std::{borrow::Cow, collections::HashMap};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    future::Future,
+    pin::Pin,
+    time::Duration,
+};
 
 use anyhow::Context;
+use chrono::{DateTime, NaiveDate, Utc};
 use colorsys::{Hsl, Rgb};
-use octocrab::Octocrab;
 use reqwest::Url;
 
+use crate::cache::Cache;
+use crate::cli::ReportFormat;
+use crate::dates::DateRange;
+use crate::github_client::GithubClient;
+use crate::metrics::format_pr_stats;
 use crate::models::{
-    GithubSearchResponse, GithubSearchResponseItem, GuardianPullRequests, TemplatePr,
+    GithubSearchResponse, GithubSearchResponseItem, SearchCategory, SearchResults, TemplatePr,
 };
+use crate::reviews::format_review;
+
+/// GitHub search stops returning results after this many items, whatever `total_count` says.
+const GITHUB_SEARCH_RESULT_CAP: u32 = 1000;
+
+/// How many times a page flagged with `incomplete_results` is requested again.
+const INCOMPLETE_RESULTS_RETRIES: u32 = 3;
 
-const GITHUB_ORG: &str = "guardian";
+/// No PR can predate GitHub itself, so this bounds searches without a start date.
+/// Searches without an end date are bounded by today.
+const GITHUB_LAUNCH_DATE: NaiveDate = match NaiveDate::from_ymd_opt(2008, 1, 1) {
+    Some(date) => date,
+    None => panic!("invalid GitHub launch date"),
+};
 
 const OPEN_PR: &str = "<svg style=\"color: #1a7f37; margin-left:10px;\" viewBox=\"0 0 16 16\" version=\"1.1\" width=\"16\" height=\"16\"
 aria-hidden=\"true\"><path fill=\"currentColor\" d=\"M7.177 3.073L9.573.677A.25.25 0 0110 .854v4.792a.25.25
@@ -29,6 +52,15 @@ aria-hidden=\"true\"><path fill=\"currentColor\" d=\"M1.5 3.25a2.25 2.25 0 1 1 3
 2.251 0 1 1-1.5 0V5a1 1 0 0 0-1-1h-1v1.646a.25.25 0 0 1-.427.177L7.177 3.427a.25.25 0 0 1 0-.354ZM3.75 2.5a.75.75
 0 1 0 0 1.5.75.75 0 0 0 0-1.5Zm0 9.5a.75.75 0 1 0 0 1.5.75.75 0 0 0 0-1.5Zm8.25.75a.75.75 0 1 0 1.5 0 .75.75 0 0 0-1.5 0Z\"></path></svg>";
 
+const OPEN_ISSUE: &str = "<svg style=\"color: #1a7f37; margin-left:10px;\" viewBox=\"0 0 16 16\" version=\"1.1\" width=\"16\" height=\"16\"
+aria-hidden=\"true\"><path fill=\"currentColor\" d=\"M8 9.5a1.5 1.5 0 1 0 0-3 1.5 1.5 0 0 0 0 3Z\"></path><path fill=\"currentColor\"
+d=\"M8 0a8 8 0 1 1 0 16A8 8 0 0 1 8 0ZM1.5 8a6.5 6.5 0 1 0 13 0 6.5 6.5 0 0 0-13 0Z\"></path></svg>";
+
+const CLOSED_ISSUE: &str = "<svg style=\"color: #8250df; margin-left:10px;\" viewBox=\"0 0 16 16\" version=\"1.1\" width=\"16\" height=\"16\"
+aria-hidden=\"true\"><path fill=\"currentColor\" d=\"M11.28 6.78a.75.75 0 0 0-1.06-1.06L7.25 8.69 5.78 7.22a.75.75 0 0 0-1.06
+1.06l2 2a.75.75 0 0 0 1.06 0l3.5-3.5Z\"></path><path fill=\"currentColor\" d=\"M16 8A8 8 0 1 1 0 8a8 8 0 0 1 16 0Zm-1.5 0a6.5
+6.5 0 1 0-13 0 6.5 6.5 0 0 0 13 0Z\"></path></svg>";
+
 pub fn prepare_parameters<'a>() -> HashMap<&'static str, Cow<'a, str>> {
     let mut params: HashMap<&'static str, Cow<str>> = HashMap::new();
     params.insert("per_page", Cow::from("100"));
@@ -47,96 +79,422 @@ pub fn calc_label_colour(colour: &str) -> String {
     hsl.to_css_string()
 }
 
-pub async fn search_pull_requests(
-    client: &Octocrab,
-    pr_type: GuardianPullRequests,
-    params: &mut HashMap<&'static str, Cow<'_, str>>,
-    from: &str,
-    to: &str,
-) -> anyhow::Result<Vec<GithubSearchResponseItem>> {
-    let mut all_results: Vec<GithubSearchResponseItem> = vec![];
-    let mut count = 1;
-
-    match pr_type {
-        GuardianPullRequests::AuthoredByMe => {
-            params.insert(
-                "q",
-                Cow::from(format!(
-                    "org:{} author:@me is:pr created:{}..{}",
-                    GITHUB_ORG, from, to
-                )),
-            );
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + 'a>>;
+
+/// One page of search results, and where the next one starts.
+pub struct SearchPage {
+    pub response: GithubSearchResponse,
+    /// Where the next page starts. `None` on the last page.
+    pub next: Option<String>,
+}
+
+/// A way of searching GitHub. Every backend returns items in the format of the REST search API,
+/// so the cache, templates and exports work the same whichever one is used.
+pub trait GithubBackend {
+    fn client(&self) -> &GithubClient;
+
+    /// What the backend's rate limit counts, e.g. "search requests".
+    fn quota_name(&self) -> &'static str;
+
+    /// Fetch a page of the results of a search query for a category,
+    /// starting at `cursor`, or at the first page without one.
+    fn search_page<'a>(
+        &'a self,
+        category: SearchCategory,
+        query: &'a str,
+        cursor: Option<&'a str>,
+    ) -> BoxFuture<'a, anyhow::Result<SearchPage>>;
+}
+
+/// Searches with the REST search API, which returns the PRs and issues without their details.
+/// Those are then fetched one PR at a time.
+pub struct RestBackend<'a> {
+    pub client: &'a GithubClient,
+}
+
+impl GithubBackend for RestBackend<'_> {
+    fn client(&self) -> &GithubClient {
+        self.client
+    }
+
+    fn quota_name(&self) -> &'static str {
+        "GitHub search requests"
+    }
+
+    fn search_page<'a>(
+        &'a self,
+        _category: SearchCategory,
+        query: &'a str,
+        cursor: Option<&'a str>,
+    ) -> BoxFuture<'a, anyhow::Result<SearchPage>> {
+        Box::pin(async move {
+            let page: u32 = cursor.and_then(|c| c.parse().ok()).unwrap_or(1);
+            let mut params = prepare_parameters();
+            params.insert("q", Cow::from(query));
+            params.insert("page", Cow::from(page.to_string()));
+
+            let response = search_page(self.client, &params, page).await?;
+            let next = match response.items.is_empty() {
+                true => None,
+                false => Some((page + 1).to_string()),
+            };
+            Ok(SearchPage { response, next })
+        })
+    }
+}
+
+/// Search every organisation for the PRs or issues in a category.
+pub async fn search_github(
+    backend: &dyn GithubBackend,
+    category: SearchCategory,
+    orgs: &[String],
+    date_range: &DateRange,
+    cache: Option<&Cache>,
+) -> anyhow::Result<SearchResults> {
+    let mut all_results = SearchResults::default();
+
+    for org in orgs {
+        let mut org_results = match cache {
+            Some(cache) => sync_org_search(backend, category, org, date_range, cache).await,
+            None => search_org(backend, category, org, date_range, None).await,
         }
-        GuardianPullRequests::ReviewedByMe => {
-            params.insert(
-                "q",
-                Cow::from(format!(
-                    "org:{} -author:@me reviewed-by:@me is:pr created:{}..{}",
-                    GITHUB_ORG, from, to
-                )),
+        .with_context(|| format!("Failed to search {} in {}", category, org))?;
+        all_results.items.append(&mut org_results.items);
+        all_results.incomplete |= org_results.incomplete;
+
+        if let Some(quota) = backend.client().quota() {
+            println!(
+                "[self-assessment] 📊 {} of {} {} left (resets at {})",
+                quota.remaining,
+                quota.limit,
+                backend.quota_name(),
+                quota.reset.with_timezone(&chrono::Local).format("%H:%M:%S")
             );
         }
     }
 
-    loop {
-        println!(
-            "[self-assessment] {} Collecting {}...",
-            match pr_type {
-                GuardianPullRequests::AuthoredByMe => "🔎",
-                GuardianPullRequests::ReviewedByMe => "🔍",
-            },
-            pr_type
+    // Results from several organisations are merged, so restore the newest-first ordering
+    all_results
+        .items
+        .sort_by_key(|r| std::cmp::Reverse(r.created_at));
+
+    if all_results.incomplete {
+        eprintln!(
+            "[self-assessment] ⚠️  GitHub could not return every {}, the report may be missing some.",
+            category
         );
+    }
+
+    Ok(all_results)
+}
+
+/// Load the PRs or issues in a category from the cache, without going online.
+pub fn cached_search_results(
+    cache: &Cache,
+    category: SearchCategory,
+    orgs: &[String],
+    date_range: &DateRange,
+) -> anyhow::Result<SearchResults> {
+    let mut all_results = SearchResults::default();
+    for org in orgs {
+        let mut org_results = cache.github_items(category.cache_key(), org, date_range)?;
+        all_results.items.append(&mut org_results);
+    }
+    all_results
+        .items
+        .sort_by_key(|r| std::cmp::Reverse(r.created_at));
+    Ok(all_results)
+}
+
+/// Bring the cache up to date with GitHub, then read the PRs or issues in the date range from it.
+/// Once a date range has been fetched in full, later runs only fetch the ones updated since.
+async fn sync_org_search(
+    backend: &dyn GithubBackend,
+    category: SearchCategory,
+    org: &str,
+    date_range: &DateRange,
+    cache: &Cache,
+) -> anyhow::Result<SearchResults> {
+    let sync_key = format!("github:{}:{}", category.cache_key(), org.to_lowercase());
+    let sync = cache.github_sync(&sync_key, date_range)?;
+    let started_at = Utc::now();
+
+    let fetched = search_org(backend, category, org, &sync.date_range, sync.updated_since).await?;
+    cache.store_github_items(category.cache_key(), org, &fetched.items)?;
+
+    // Partial data has to be fetched again in full next time
+    if !fetched.incomplete {
+        let full = sync.updated_since.is_none();
+        if full {
+            cache.remove_other_github_items(
+                category.cache_key(),
+                org,
+                &sync.date_range,
+                &fetched.items,
+            )?;
+        }
+        cache.finish_github_sync(&sync_key, &sync.date_range, started_at, full)?;
+    }
+
+    Ok(SearchResults {
+        items: cache.github_items(category.cache_key(), org, date_range)?,
+        incomplete: fetched.incomplete,
+    })
+}
+
+async fn search_org(
+    backend: &dyn GithubBackend,
+    category: SearchCategory,
+    org: &str,
+    date_range: &DateRange,
+    updated_since: Option<DateTime<Utc>>,
+) -> anyhow::Result<SearchResults> {
+    let mut all_results = SearchResults::default();
+    let mut seen_ids: HashSet<u64> = HashSet::new();
+
+    // Issues opened by the user are left out of the other issue categories,
+    // just as their own PRs are left out of the reviewed ones
+    let query = match category {
+        SearchCategory::AuthoredByMe => format!("org:{} author:@me is:pr", org),
+        SearchCategory::ReviewedByMe => {
+            format!("org:{} -author:@me reviewed-by:@me is:pr", org)
+        }
+        SearchCategory::IssuesOpenedByMe => format!("org:{} author:@me is:issue", org),
+        SearchCategory::IssuesCommentedOnByMe => {
+            format!("org:{} -author:@me commenter:@me is:issue", org)
+        }
+        SearchCategory::IssuesAssignedToMe => {
+            format!("org:{} -author:@me assignee:@me is:issue", org)
+        }
+    };
+
+    let query = match updated_since {
+        Some(updated_since) => format!(
+            "{} updated:>={}",
+            query,
+            updated_since.format("%Y-%m-%dT%H:%M:%SZ")
+        ),
+        None => query,
+    };
 
-        params.insert("page", Cow::from(count.to_string()));
-        let mut response: GithubSearchResponse = client
-            .get("/search/issues", Some(&params))
+    println!(
+        "[self-assessment] {} Collecting {} in {}{}...",
+        match category {
+            SearchCategory::AuthoredByMe => "🔎",
+            SearchCategory::ReviewedByMe => "🔍",
+            SearchCategory::IssuesOpenedByMe => "📝",
+            SearchCategory::IssuesCommentedOnByMe => "💬",
+            SearchCategory::IssuesAssignedToMe => "📌",
+        },
+        category,
+        org,
+        match updated_since {
+            Some(updated_since) => format!(
+                " updated since {}",
+                updated_since
+                    .with_timezone(&chrono::Local)
+                    .format("%Y-%m-%d %H:%M")
+            ),
+            None => String::new(),
+        }
+    );
+
+    // GitHub search never returns more than 1000 results, so windows holding more than that
+    // are split in half and searched again until every window fits under the cap
+    let mut windows = vec![(
+        date_range.from.unwrap_or(GITHUB_LAUNCH_DATE),
+        date_range.to.unwrap_or_else(|| Utc::now().date_naive()),
+    )];
+
+    while let Some((window_from, window_to)) = windows.pop() {
+        let window_query = format!("{} created:{}..{}", query, window_from, window_to);
+        let mut cursor: Option<String> = None;
+        let mut window_results: Vec<GithubSearchResponseItem> = vec![];
+
+        loop {
+            let SearchPage { mut response, next } = backend
+                .search_page(category, &window_query, cursor.as_deref())
+                .await?;
+
+            if response.total_count > GITHUB_SEARCH_RESULT_CAP && window_from < window_to {
+                let midpoint = window_from + (window_to - window_from) / 2;
+                println!(
+                    "[self-assessment] ✂️  {} results between {} and {}, splitting the search...",
+                    response.total_count, window_from, window_to
+                );
+                windows.push((midpoint.succ_opt().unwrap_or(midpoint), window_to));
+                windows.push((window_from, midpoint));
+                window_results.clear();
+                break;
+            }
+
+            all_results.incomplete |= response.incomplete_results;
+            let received = response.items.len();
+            window_results.append(&mut response.items);
+
+            if received == 0
+                || next.is_none()
+                || window_results.len()
+                    >= response.total_count.min(GITHUB_SEARCH_RESULT_CAP) as usize
+            {
+                if response.total_count > GITHUB_SEARCH_RESULT_CAP {
+                    all_results.incomplete = true;
+                    eprintln!(
+                        "[self-assessment] ⚠️  More than {} results on {}, only the first {} are included.",
+                        GITHUB_SEARCH_RESULT_CAP, window_from, GITHUB_SEARCH_RESULT_CAP
+                    );
+                }
+                break;
+            }
+            cursor = next;
+        }
+
+        for item in window_results {
+            if seen_ids.insert(item.id) {
+                all_results.items.push(item);
+            }
+        }
+    }
+
+    Ok(all_results)
+}
+
+/// Fetch one page of search results.
+/// GitHub sets `incomplete_results` when the search timed out before every match was found,
+/// in which case the page is requested again a few times before settling for partial data.
+async fn search_page(
+    client: &GithubClient,
+    params: &HashMap<&'static str, Cow<'_, str>>,
+    page: u32,
+) -> anyhow::Result<GithubSearchResponse> {
+    let mut retries = 0;
+    loop {
+        let response: GithubSearchResponse = client
+            .get("/search/issues", params)
             .await
             .context("Failed to search issues")?;
-        all_results.append(&mut response.items);
-        count += 1;
-        if all_results.len() == response.total_count as usize {
-            break;
+
+        if !response.incomplete_results || retries == INCOMPLETE_RESULTS_RETRIES {
+            return Ok(response);
         }
+
+        retries += 1;
+        println!(
+            "[self-assessment] ⏳ GitHub timed out searching page {}, retrying ({} of {})...",
+            page, retries, INCOMPLETE_RESULTS_RETRIES
+        );
+        tokio::time::sleep(Duration::from_secs(2 * retries as u64)).await;
     }
+}
 
-    Ok(all_results)
+/// Split a repository API URL into the `owner/repo` full name. The URL can be on any host, with
+/// the API at its root (`https://api.github.com/repos/{owner}/{repo}`) or under a path
+/// (`https://github.example.com/api/v3/repos/{owner}/{repo}`).
+pub fn repo_full_name(repository_url: &str) -> Option<String> {
+    let url = Url::parse(repository_url).ok()?;
+    let segments: Vec<&str> = url.path_segments()?.filter(|s| !s.is_empty()).collect();
+    match segments.as_slice() {
+        [.., "repos", owner, repo] => Some(format!("{}/{}", owner, repo)),
+        _ => None,
+    }
 }
 
-pub fn format_prs(results: &[GithubSearchResponseItem]) -> Vec<TemplatePr> {
-    let base = Url::parse(&format!("https://api.github.com/repos/{}/", GITHUB_ORG)).unwrap();
+/// Turn a pull request or issue web URL (`.../{owner}/{repo}/pull/{number}`)
+/// into the repository web URL.
+pub fn repo_html_url(pr_html_url: &str) -> Option<String> {
+    let mut url = Url::parse(pr_html_url).ok()?;
+    url.path_segments_mut().ok()?.pop_if_empty().pop().pop();
+    Some(url.to_string())
+}
 
+/// Format search results, PRs or issues, for the report template.
+/// The status, labels and body are HTML fragments for HTML reports, and plain Markdown otherwise.
+pub fn format_prs<'a>(
+    results: impl IntoIterator<Item = &'a GithubSearchResponseItem>,
+    format: ReportFormat,
+) -> Vec<TemplatePr> {
     results
-    .iter()
+    .into_iter()
     .map(|r| {
-        let url = Url::parse(&r.repository_url.to_string()).unwrap();
-        let repo_name = base.make_relative(&url).unwrap();
-        let status = match r.state.as_str() {
-            "open" => OPEN_PR.to_string(),
-            "closed" => if r.pull_request.merged_at.is_some() { MERGED_PR.to_string() } else { CLOSED_PR.to_string() },
-            _ => r.state.to_string(),
+        let repo_name = repo_full_name(&r.repository_url).unwrap_or_else(|| r.repository_url.to_string());
+        let repo_url = repo_html_url(&r.html_url).unwrap_or_else(|| r.html_url.to_string());
+        let body = match &r.body {
+            Some(body) if !body.trim().is_empty() => body.as_str(),
+            _ => "*No description provided.*",
         };
 
-        TemplatePr {
-            status,
-            created_at: r.created_at.format("%Y-%m-%d").to_string(),
-            title: r.title.to_string(),
-            html_url: r.html_url.to_string(),
-            repo_name,
-            comments: r.comments,
-            comments_present: (r.comments > 0, r.comments == 1),
-            body: markdown::to_html(match &r.body {
-                Some(body) => body,
-                None => "*No description provided.*",
-            }),
-            labels: r.labels.iter()
-                .map(|l| format!("<span class=\"label\" style=\"color:{}; background-color: #{};\">{}</span>",
-                calc_label_colour(&String::from(&l.color)),&l.color,&l.name))
-                .collect::<Vec<String>>()
-                .join(" "),
-            author: r.user.login.to_string(),
-            profile_pic: r.user.avatar_url.to_string(),
+        match format {
+            ReportFormat::Markdown => TemplatePr {
+                status: match r.state.as_str() {
+                    "open" => "Open".to_string(),
+                    "closed" => if merged_at(r).is_some() { "Merged".to_string() } else { "Closed".to_string() },
+                    _ => r.state.to_string(),
+                },
+                created_at: r.created_at.format("%Y-%m-%d").to_string(),
+                title: escape_markdown(&r.title),
+                html_url: r.html_url.to_string(),
+                repo_name,
+                repo_url,
+                comments: r.comments,
+                comments_present: (r.comments > 0, r.comments == 1),
+                // Quote the description so its headings and lists stay nested under the PR
+                body: body.lines().map(|l| format!("  > {}", l).trim_end().to_string()).collect::<Vec<String>>().join("\n"),
+                labels: r.labels.iter()
+                    .map(|l| format!("`{}`", l.name.replace('`', "'")))
+                    .collect::<Vec<String>>()
+                    .join(" "),
+                author: r.user.login.to_string(),
+                profile_pic: r.user.avatar_url.to_string(),
+                review: format_review(r, format),
+                stats: format_pr_stats(r),
+            },
+            _ => TemplatePr {
+                status: match (r.state.as_str(), &r.pull_request) {
+                    ("open", Some(_)) => OPEN_PR.to_string(),
+                    ("closed", Some(_)) => if merged_at(r).is_some() { MERGED_PR.to_string() } else { CLOSED_PR.to_string() },
+                    ("open", None) => OPEN_ISSUE.to_string(),
+                    ("closed", None) => CLOSED_ISSUE.to_string(),
+                    _ => r.state.to_string(),
+                },
+                created_at: r.created_at.format("%Y-%m-%d").to_string(),
+                title: r.title.to_string(),
+                html_url: r.html_url.to_string(),
+                repo_name,
+                repo_url,
+                comments: r.comments,
+                comments_present: (r.comments > 0, r.comments == 1),
+                body: markdown::to_html(body),
+                labels: r.labels.iter()
+                    .map(|l| format!("<span class=\"label\" style=\"color:{}; background-color: #{};\">{}</span>",
+                    calc_label_colour(&String::from(&l.color)),&l.color,&l.name))
+                    .collect::<Vec<String>>()
+                    .join(" "),
+                author: r.user.login.to_string(),
+                profile_pic: r.user.avatar_url.to_string(),
+                review: format_review(r, format),
+                stats: format_pr_stats(r),
+            },
         }
     })
     .collect()
 }
+
+/// When a PR was merged. Always `None` for issues.
+pub fn merged_at(item: &GithubSearchResponseItem) -> Option<DateTime<Utc>> {
+    item.pull_request.as_ref().and_then(|pr| pr.merged_at)
+}
+
+/// Escape the characters that would otherwise break a Markdown link text or list item.
+pub fn escape_markdown(text: &str) -> String {
+    text.chars()
+        .fold(String::with_capacity(text.len()), |mut escaped, c| {
+            if matches!(
+                c,
+                '\\' | '[' | ']' | '*' | '_' | '`' | '<' | '>' | '#' | '|'
+            ) {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+            escaped
+        })
+}
@@ -0,0 +1,269 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use octocrab::Octocrab;
+use serde::{de::DeserializeOwned, Deserialize};
+
+use crate::github_host::GithubHost;
+
+/// How many times a rate-limited request is retried before giving up.
+const MAX_RETRIES: u32 = 5;
+
+/// GitHub asks clients to wait at least a minute before retrying after a secondary rate limit
+/// that doesn't come with a `Retry-After` header, doubling the wait on each further attempt.
+const SECONDARY_RATE_LIMIT_BACKOFF_SECS: u64 = 60;
+
+/// The most items GitHub returns in one page of a list endpoint.
+const PAGE_SIZE: usize = 100;
+
+/// The quota reported by GitHub in the `X-RateLimit-*` headers of the last response.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    pub limit: u32,
+    pub remaining: u32,
+    pub reset: DateTime<Utc>,
+}
+
+/// The body of a GraphQL response. GitHub can return partial `data` along with `errors`.
+#[derive(Deserialize)]
+struct GraphqlResponse<T> {
+    data: Option<T>,
+    errors: Option<Vec<GraphqlError>>,
+}
+
+#[derive(Deserialize)]
+struct GraphqlError {
+    message: String,
+}
+
+/// The `data` of a GraphQL response.
+pub struct GraphqlData<T> {
+    pub data: T,
+    /// Whether GitHub reported errors for parts of the data it left out, e.g. search results
+    /// in organisations the token isn't authorised for with SAML single sign-on.
+    pub partial: bool,
+}
+
+/// Wraps an [`Octocrab`] client, pausing when the rate limit is used up and backing off
+/// when GitHub answers with a primary or secondary rate-limit error.
+pub struct GithubClient {
+    pub octocrab: Octocrab,
+    graphql_octocrab: Octocrab,
+    pub host: GithubHost,
+    quota: Mutex<Option<RateLimit>>,
+    requests: AtomicU32,
+}
+
+impl GithubClient {
+    /// A client for the REST and GraphQL APIs of `host`, authenticated with `token`.
+    pub fn new(host: GithubHost, token: &str) -> anyhow::Result<Self> {
+        Ok(GithubClient {
+            octocrab: host.octocrab(token)?,
+            graphql_octocrab: host.graphql_octocrab(token)?,
+            host,
+            quota: Mutex::new(None),
+            requests: AtomicU32::new(0),
+        })
+    }
+
+    /// The quota reported by the most recent response, if any.
+    pub fn quota(&self) -> Option<RateLimit> {
+        *self.quota.lock().unwrap()
+    }
+
+    /// Number of requests sent to GitHub so far, including retries.
+    pub fn request_count(&self) -> u32 {
+        self.requests.load(Ordering::Relaxed)
+    }
+
+    /// Send a `GET` request to `route` with the given query parameters and deserialise the body,
+    /// retrying with exponential backoff when GitHub rate-limits the request.
+    pub async fn get<T: DeserializeOwned>(
+        &self,
+        route: &str,
+        params: &HashMap<&'static str, Cow<'_, str>>,
+    ) -> anyhow::Result<T> {
+        let query = url::form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(params.iter().map(|(k, v)| (*k, v.as_ref())))
+            .finish();
+        let uri = format!("{}?{}", route, query);
+
+        let body = self.send(route, &uri, None).await?;
+        serde_json::from_str(&body)
+            .with_context(|| format!("Failed to parse the response from {}", route))
+    }
+
+    /// Run a GraphQL query and deserialise its `data`, with the same rate-limit handling as
+    /// [`GithubClient::get`]. Errors reported alongside the data are printed as warnings and
+    /// the data is flagged as partial. Errors without any data are turned into an error.
+    pub async fn graphql<T: DeserializeOwned>(
+        &self,
+        query: &str,
+        variables: serde_json::Value,
+    ) -> anyhow::Result<GraphqlData<T>> {
+        let request = serde_json::json!({ "query": query, "variables": variables });
+        let url = self.host.graphql_url();
+        let body = self.send(&url, &url, Some(&request)).await?;
+        let response: GraphqlResponse<T> = serde_json::from_str(&body)
+            .context("Failed to parse the response from the GraphQL API")?;
+
+        let errors = response.errors.unwrap_or_default();
+        match response.data {
+            Some(data) => {
+                for error in &errors {
+                    eprintln!(
+                        "[self-assessment] ⚠️  The GraphQL API left out some results: {}",
+                        error.message
+                    );
+                }
+                Ok(GraphqlData {
+                    data,
+                    partial: !errors.is_empty(),
+                })
+            }
+            None if !errors.is_empty() => anyhow::bail!(
+                "The GraphQL API returned errors: {}",
+                errors
+                    .iter()
+                    .map(|e| e.message.as_str())
+                    .collect::<Vec<&str>>()
+                    .join("; ")
+            ),
+            None => anyhow::bail!("The GraphQL API returned no data"),
+        }
+    }
+
+    /// Send a request, `POST`ing `body` to the GraphQL API if there is one and `GET`ting `uri`
+    /// from the REST API otherwise, and return the response body once GitHub stops
+    /// rate-limiting it.
+    async fn send(
+        &self,
+        route: &str,
+        uri: &str,
+        body: Option<&serde_json::Value>,
+    ) -> anyhow::Result<String> {
+        let mut attempt = 0;
+        loop {
+            self.wait_for_quota().await;
+
+            self.requests.fetch_add(1, Ordering::Relaxed);
+            let response = match body {
+                Some(body) => self.graphql_octocrab._post(uri, Some(body)).await,
+                None => self.octocrab._get(uri).await,
+            }
+            .with_context(|| format!("Failed to send request to {}", route))?;
+
+            let status = response.status().as_u16();
+            let headers = response.headers();
+            let rate_limit = parse_rate_limit(headers);
+            let retry_after = header_value::<u64>(headers, "retry-after");
+            if rate_limit.is_some() {
+                *self.quota.lock().unwrap() = rate_limit;
+            }
+            let body = self.octocrab.body_to_string(response).await?;
+
+            // Secondary rate limits come back as a plain 403, only recognisable by the message
+            let rate_limited = status == 429
+                || (status == 403
+                    && (retry_after.is_some()
+                        || rate_limit.is_some_and(|r| r.remaining == 0)
+                        || body.to_lowercase().contains("rate limit")));
+
+            if rate_limited && attempt < MAX_RETRIES {
+                attempt += 1;
+                let wait = match (retry_after, rate_limit) {
+                    (Some(secs), _) => Duration::from_secs(secs),
+                    (None, Some(r)) if r.remaining == 0 => until(r.reset),
+                    _ => Duration::from_secs(
+                        SECONDARY_RATE_LIMIT_BACKOFF_SECS * 2u64.pow(attempt - 1),
+                    ),
+                };
+                println!(
+                    "[self-assessment] ⏳ Hit GitHub's rate limit, retrying in {}s (attempt {} of {})...",
+                    wait.as_secs(),
+                    attempt,
+                    MAX_RETRIES
+                );
+                tokio::time::sleep(wait).await;
+                continue;
+            }
+
+            if !(200..300).contains(&status) {
+                let message = serde_json::from_str::<serde_json::Value>(&body)
+                    .ok()
+                    .and_then(|v| v["message"].as_str().map(|m| m.to_string()))
+                    .unwrap_or(body);
+                anyhow::bail!("GitHub returned {} for {}: {}", status, route, message);
+            }
+
+            return Ok(body);
+        }
+    }
+
+    /// Fetch every page of a list endpoint, such as the reviews of a PR.
+    pub async fn get_all_pages<T: DeserializeOwned>(&self, route: &str) -> anyhow::Result<Vec<T>> {
+        let mut params: HashMap<&'static str, Cow<str>> = HashMap::new();
+        params.insert("per_page", Cow::from(PAGE_SIZE.to_string()));
+
+        let mut all_items = vec![];
+        for page in 1.. {
+            params.insert("page", Cow::from(page.to_string()));
+            let mut items: Vec<T> = self.get(route, &params).await?;
+            let last_page = items.len() < PAGE_SIZE;
+            all_items.append(&mut items);
+            if last_page {
+                break;
+            }
+        }
+        Ok(all_items)
+    }
+
+    /// Sleep until the quota resets if the last response said it was used up.
+    async fn wait_for_quota(&self) {
+        if let Some(rate_limit) = self.quota() {
+            if rate_limit.remaining == 0 && rate_limit.reset > Utc::now() {
+                let wait = until(rate_limit.reset);
+                println!(
+                    "[self-assessment] ⏳ GitHub quota used up, waiting {}s for it to reset...",
+                    wait.as_secs()
+                );
+                tokio::time::sleep(wait).await;
+            }
+        }
+    }
+}
+
+fn header_value<T: std::str::FromStr>(
+    headers: &reqwest::header::HeaderMap,
+    name: &str,
+) -> Option<T> {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse().ok())
+}
+
+fn parse_rate_limit(headers: &reqwest::header::HeaderMap) -> Option<RateLimit> {
+    Some(RateLimit {
+        limit: header_value(headers, "x-ratelimit-limit")?,
+        remaining: header_value(headers, "x-ratelimit-remaining")?,
+        reset: DateTime::from_timestamp(header_value(headers, "x-ratelimit-reset")?, 0)?,
+    })
+}
+
+/// Time left until `instant`, plus a second of slack so the reset has definitely happened.
+fn until(instant: DateTime<Utc>) -> Duration {
+    (instant - Utc::now())
+        .to_std()
+        .unwrap_or_default()
+        .saturating_add(Duration::from_secs(1))
+}
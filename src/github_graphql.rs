@@ -0,0 +1,690 @@
+use chrono::{DateTime, Utc};
+use reqwest::Url;
+use serde::Deserialize;
+
+use crate::github::{BoxFuture, GithubBackend, SearchPage};
+use crate::github_client::{GithubClient, GraphqlData};
+use crate::github_host::GithubHost;
+use crate::models::{
+    GithubSearchResponse, GithubSearchResponseItem, Label, PullRequest, PullRequestStats,
+    ReviewActivity, SearchCategory, User,
+};
+
+/// Ghost is the user GitHub shows in place of deleted accounts.
+const GHOST_USER_LOGIN: &str = "ghost";
+const GHOST_USER_ID: u64 = 10137;
+const GHOST_USER_NODE_ID: &str = "MDQ6VXNlcjEwMTM3";
+
+/// Fields of the `Actor` interface, e.g. an author, which may be a user, a bot or a mannequin.
+macro_rules! actor_fields {
+    () => {
+        "__typename login avatarUrl url
+         ... on User { id databaseId isSiteAdmin }
+         ... on Bot { id databaseId }
+         ... on Mannequin { id databaseId }
+         ... on Organization { id databaseId }"
+    };
+}
+
+macro_rules! item_fields {
+    () => {
+        concat!(
+            "databaseId id number title url body createdAt updatedAt closedAt
+             locked authorAssociation
+             author { ",
+            actor_fields!(),
+            " }
+             repository { nameWithOwner }
+             labels(first: 50) { nodes { id name color } }
+             assignees(first: 1) {
+               nodes { __typename login avatarUrl url id databaseId isSiteAdmin }
+             }
+             comments { totalCount }"
+        )
+    };
+}
+
+/// Searches PRs and issues along with everything the report needs about them, so a page of
+/// 100 results costs a single request instead of one search plus a few requests per PR.
+const SEARCH_QUERY: &str = concat!(
+    "query($query: String!, $after: String) {
+       search(query: $query, type: ISSUE, first: 100, after: $after) {
+         issueCount
+         pageInfo { hasNextPage endCursor }
+         nodes {
+           ... on Issue { issueState: state ",
+    item_fields!(),
+    " }
+           ... on PullRequest { pullRequestState: state ",
+    item_fields!(),
+    " mergedAt additions deletions changedFiles
+             commits { totalCount }
+             reviews(first: 100) {
+               nodes { author { login } state submittedAt comments { totalCount } }
+             }
+           }
+         }
+       }
+     }"
+);
+
+#[derive(Deserialize)]
+struct SearchData {
+    search: Search,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Search {
+    issue_count: u32,
+    page_info: PageInfo,
+    /// `null` for results GitHub reported an error for instead.
+    nodes: Vec<Option<SearchNode>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PageInfo {
+    has_next_page: bool,
+    end_cursor: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Nodes<T> {
+    nodes: Vec<T>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TotalCount {
+    total_count: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SearchNode {
+    database_id: u64,
+    id: String,
+    number: u32,
+    title: String,
+    url: String,
+    /// Issues and PRs have different types of state, so each is fetched under its own name.
+    #[serde(rename = "issueState", alias = "pullRequestState")]
+    state: String,
+    body: String,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    closed_at: Option<DateTime<Utc>>,
+    locked: bool,
+    author_association: String,
+    /// `null` when the author's account was deleted.
+    author: Option<Actor>,
+    repository: Repository,
+    labels: Nodes<GraphqlLabel>,
+    assignees: Nodes<Actor>,
+    comments: TotalCount,
+    /// The rest are only returned for PRs.
+    merged_at: Option<DateTime<Utc>>,
+    additions: Option<u32>,
+    deletions: Option<u32>,
+    changed_files: Option<u32>,
+    commits: Option<TotalCount>,
+    reviews: Option<Nodes<GraphqlReview>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Actor {
+    #[serde(rename = "__typename")]
+    typename: String,
+    login: String,
+    avatar_url: String,
+    url: String,
+    id: Option<String>,
+    database_id: Option<u64>,
+    #[serde(default)]
+    is_site_admin: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Repository {
+    name_with_owner: String,
+}
+
+#[derive(Deserialize)]
+struct GraphqlLabel {
+    id: String,
+    name: String,
+    color: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphqlReview {
+    author: Option<ReviewAuthor>,
+    state: String,
+    submitted_at: Option<DateTime<Utc>>,
+    comments: TotalCount,
+}
+
+#[derive(Deserialize)]
+struct ReviewAuthor {
+    login: String,
+}
+
+/// Searches with the GraphQL API, fetching the details of PRs in the same queries.
+pub struct GraphqlBackend<'a> {
+    pub client: &'a GithubClient,
+    /// The user the report is about, to tell their reviews apart from others'.
+    pub login: String,
+}
+
+impl GithubBackend for GraphqlBackend<'_> {
+    fn client(&self) -> &GithubClient {
+        self.client
+    }
+
+    fn quota_name(&self) -> &'static str {
+        "GitHub GraphQL points"
+    }
+
+    fn search_page<'a>(
+        &'a self,
+        category: SearchCategory,
+        query: &'a str,
+        cursor: Option<&'a str>,
+    ) -> BoxFuture<'a, anyhow::Result<SearchPage>> {
+        Box::pin(async move {
+            let response: GraphqlData<SearchData> = self
+                .client
+                .graphql(
+                    SEARCH_QUERY,
+                    serde_json::json!({ "query": query, "after": cursor }),
+                )
+                .await?;
+            let search = response.data.search;
+
+            Ok(SearchPage {
+                response: GithubSearchResponse {
+                    total_count: search.issue_count,
+                    // GraphQL doesn't say whether the search timed out,
+                    // but it does say when it left results out
+                    incomplete_results: response.partial,
+                    items: search
+                        .nodes
+                        .into_iter()
+                        .flatten()
+                        .map(|node| search_item(node, category, &self.login, &self.client.host))
+                        .collect(),
+                },
+                next: match search.page_info.has_next_page {
+                    true => search.page_info.end_cursor,
+                    false => None,
+                },
+            })
+        })
+    }
+}
+
+/// Turn a GraphQL search result into the format of the REST search API.
+/// GraphQL only returns web URLs, so the REST URLs are built from the host's API URL.
+fn search_item(
+    node: SearchNode,
+    category: SearchCategory,
+    login: &str,
+    host: &GithubHost,
+) -> GithubSearchResponseItem {
+    let repo_api_url = format!("{}/repos/{}", host.api_url, node.repository.name_with_owner);
+    let url = format!("{}/issues/{}", repo_api_url, node.number);
+
+    // Only PRs have commits
+    let pull_request = node.commits.as_ref().map(|_| PullRequest {
+        url: format!("{}/pulls/{}", repo_api_url, node.number),
+        html_url: node.url.to_string(),
+        diff_url: format!("{}.diff", node.url),
+        patch_url: format!("{}.patch", node.url),
+        merged_at: node.merged_at,
+    });
+
+    let reviews = node.reviews.map(|r| r.nodes).unwrap_or_default();
+    let is_mine = |review: &&GraphqlReview| {
+        review
+            .author
+            .as_ref()
+            .is_some_and(|a| a.login.eq_ignore_ascii_case(login))
+    };
+
+    // Only the details the REST backend would have fetched for the category are filled in
+    let pr_stats = match (category, &node.commits) {
+        (SearchCategory::AuthoredByMe, Some(commits)) => Some(PullRequestStats {
+            additions: node.additions.unwrap_or_default(),
+            deletions: node.deletions.unwrap_or_default(),
+            changed_files: node.changed_files.unwrap_or_default(),
+            commits: commits.total_count,
+            first_review_at: reviews
+                .iter()
+                .filter(|r| r.author.is_some() && !is_mine(r))
+                .filter_map(|r| r.submitted_at)
+                .min(),
+        }),
+        _ => None,
+    };
+    let review_activity = match category {
+        SearchCategory::ReviewedByMe => {
+            let my_reviews: Vec<&GraphqlReview> = reviews
+                .iter()
+                .filter(|r| is_mine(r) && r.state != "PENDING")
+                .collect();
+            Some(ReviewActivity {
+                states: my_reviews.iter().map(|r| r.state.to_string()).collect(),
+                // Inline comments are always part of a review
+                inline_comments: my_reviews.iter().map(|r| r.comments.total_count).sum(),
+                first_response_at: my_reviews.iter().filter_map(|r| r.submitted_at).min(),
+            })
+        }
+        _ => None,
+    };
+
+    GithubSearchResponseItem {
+        labels_url: format!("{}/labels{{/name}}", url),
+        comments_url: format!("{}/comments", url),
+        events_url: format!("{}/events", url),
+        url,
+        html_url: node.url,
+        id: node.database_id,
+        node_id: node.id,
+        number: node.number,
+        title: node.title,
+        user: rest_user(node.author, host),
+        labels: node
+            .labels
+            .nodes
+            .into_iter()
+            .map(|l| Label {
+                id: None,
+                node_id: l.id,
+                url: label_api_url(&repo_api_url, &l.name),
+                name: l.name,
+                color: l.color,
+            })
+            .collect(),
+        // Merged PRs are closed as far as the REST API is concerned
+        state: match node.state.as_str() {
+            "OPEN" => "open".to_string(),
+            _ => "closed".to_string(),
+        },
+        assignee: node
+            .assignees
+            .nodes
+            .into_iter()
+            .next()
+            .map(|assignee| rest_user(Some(assignee), host)),
+        // Not returned by the GraphQL API, so left out of JSON exports
+        milestone: None,
+        comments: node.comments.total_count,
+        created_at: node.created_at,
+        updated_at: node.updated_at,
+        closed_at: node.closed_at,
+        pull_request,
+        body: Some(node.body),
+        score: None,
+        locked: node.locked,
+        author_association: node.author_association,
+        repository_url: repo_api_url,
+        review_activity,
+        pr_stats,
+    }
+}
+
+/// Turn a GraphQL actor into a REST user, or the ghost user if the account was deleted.
+fn rest_user(actor: Option<Actor>, host: &GithubHost) -> User {
+    let actor = actor.unwrap_or_else(|| Actor {
+        typename: "User".to_string(),
+        login: GHOST_USER_LOGIN.to_string(),
+        avatar_url: "https://avatars.githubusercontent.com/u/10137?v=4".to_string(),
+        url: format!("{}/{}", host.web_url, GHOST_USER_LOGIN),
+        id: Some(GHOST_USER_NODE_ID.to_string()),
+        database_id: Some(GHOST_USER_ID),
+        is_site_admin: false,
+    });
+    let api_url = format!("{}/users/{}", host.api_url, actor.login);
+
+    User {
+        id: actor.database_id.unwrap_or_default(),
+        node_id: actor.id.unwrap_or_default(),
+        avatar_url: actor.avatar_url,
+        gravatar_id: String::new(),
+        html_url: actor.url,
+        followers_url: format!("{}/followers", api_url),
+        gists_url: format!("{}/gists{{/gist_id}}", api_url),
+        starred_url: format!("{}/starred{{/owner}}{{/repo}}", api_url),
+        subscriptions_url: format!("{}/subscriptions", api_url),
+        organizations_url: format!("{}/orgs", api_url),
+        repos_url: format!("{}/repos", api_url),
+        events_url: format!("{}/events{{/privacy}}", api_url),
+        received_events_url: format!("{}/received_events", api_url),
+        url: api_url,
+        login: actor.login,
+        r#type: actor.typename,
+        site_admin: actor.is_site_admin,
+    }
+}
+
+/// The REST URL of a label, with its name percent-encoded like GitHub does.
+fn label_api_url(repo_api_url: &str, name: &str) -> String {
+    let mut url = match Url::parse(repo_api_url) {
+        Ok(url) => url,
+        Err(_) => return format!("{}/labels/{}", repo_api_url, name),
+    };
+    if let Ok(mut segments) = url.path_segments_mut() {
+        segments.push("labels").push(name);
+    }
+    url.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::github_host::{DEFAULT_GITHUB_API_URL, DEFAULT_GITHUB_URL};
+
+    fn github_com() -> GithubHost {
+        GithubHost {
+            api_url: DEFAULT_GITHUB_API_URL.to_string(),
+            web_url: DEFAULT_GITHUB_URL.to_string(),
+        }
+    }
+
+    #[test]
+    fn search_query_has_the_expected_shape() {
+        let expected = "query($query: String!, $after: String) {
+           search(query: $query, type: ISSUE, first: 100, after: $after) {
+             issueCount
+             pageInfo { hasNextPage endCursor }
+             nodes {
+               ... on Issue { issueState: state
+                 databaseId id number title url body createdAt updatedAt closedAt
+                 locked authorAssociation
+                 author { __typename login avatarUrl url
+                   ... on User { id databaseId isSiteAdmin }
+                   ... on Bot { id databaseId }
+                   ... on Mannequin { id databaseId }
+                   ... on Organization { id databaseId } }
+                 repository { nameWithOwner }
+                 labels(first: 50) { nodes { id name color } }
+                 assignees(first: 1) {
+                   nodes { __typename login avatarUrl url id databaseId isSiteAdmin }
+                 }
+                 comments { totalCount } }
+               ... on PullRequest { pullRequestState: state
+                 databaseId id number title url body createdAt updatedAt closedAt
+                 locked authorAssociation
+                 author { __typename login avatarUrl url
+                   ... on User { id databaseId isSiteAdmin }
+                   ... on Bot { id databaseId }
+                   ... on Mannequin { id databaseId }
+                   ... on Organization { id databaseId } }
+                 repository { nameWithOwner }
+                 labels(first: 50) { nodes { id name color } }
+                 assignees(first: 1) {
+                   nodes { __typename login avatarUrl url id databaseId isSiteAdmin }
+                 }
+                 comments { totalCount } mergedAt additions deletions changedFiles
+                 commits { totalCount }
+                 reviews(first: 100) {
+                   nodes { author { login } state submittedAt comments { totalCount } }
+                 }
+               }
+             }
+           }
+         }";
+        let normalise = |query: &str| query.split_whitespace().collect::<Vec<&str>>().join(" ");
+        assert_eq!(normalise(SEARCH_QUERY), normalise(expected));
+    }
+
+    /// A page of GraphQL search results: a PR, a result GitHub couldn't return and an issue
+    /// whose author's account was deleted.
+    fn graphql_response() -> Value {
+        let octo = json!({
+            "__typename": "User",
+            "login": "octo",
+            "avatarUrl": "https://avatars.githubusercontent.com/u/1?v=4",
+            "url": "https://github.com/octo",
+            "id": "MDQ6VXNlcjE=",
+            "databaseId": 1,
+            "isSiteAdmin": false
+        });
+        json!({
+            "search": {
+                "issueCount": 3,
+                "pageInfo": { "hasNextPage": false, "endCursor": "Y3Vyc29yOjM=" },
+                "nodes": [
+                    {
+                        "pullRequestState": "MERGED",
+                        "databaseId": 1001,
+                        "id": "PR_kwDOAAABcs5",
+                        "number": 42,
+                        "title": "Add charts",
+                        "url": "https://github.com/guardian/frontend/pull/42",
+                        "body": "Adds **charts**",
+                        "createdAt": "2026-04-01T10:00:00Z",
+                        "updatedAt": "2026-04-03T09:00:00Z",
+                        "closedAt": "2026-04-02T10:00:00Z",
+                        "locked": false,
+                        "authorAssociation": "MEMBER",
+                        "author": octo,
+                        "repository": { "nameWithOwner": "guardian/frontend" },
+                        "labels": { "nodes": [
+                            { "id": "LA_kwDOAAABcs8", "name": "good first issue", "color": "7057ff" }
+                        ] },
+                        "assignees": { "nodes": [] },
+                        "comments": { "totalCount": 2 },
+                        "mergedAt": "2026-04-02T10:00:00Z",
+                        "additions": 120,
+                        "deletions": 7,
+                        "changedFiles": 4,
+                        "commits": { "totalCount": 3 },
+                        "reviews": { "nodes": [
+                            { "author": { "login": "octo" }, "state": "COMMENTED",
+                              "submittedAt": "2026-04-01T11:00:00Z", "comments": { "totalCount": 0 } },
+                            { "author": { "login": "alice" }, "state": "APPROVED",
+                              "submittedAt": "2026-04-01T16:00:00Z", "comments": { "totalCount": 1 } }
+                        ] }
+                    },
+                    null,
+                    {
+                        "issueState": "CLOSED",
+                        "databaseId": 2002,
+                        "id": "I_kwDOAAABcs6",
+                        "number": 7,
+                        "title": "Charts are blurry",
+                        "url": "https://github.com/guardian/frontend/issues/7",
+                        "body": "",
+                        "createdAt": "2026-03-01T08:30:00Z",
+                        "updatedAt": "2026-03-05T12:00:00Z",
+                        "closedAt": "2026-03-05T12:00:00Z",
+                        "locked": false,
+                        "authorAssociation": "NONE",
+                        "author": null,
+                        "repository": { "nameWithOwner": "guardian/frontend" },
+                        "labels": { "nodes": [] },
+                        "assignees": { "nodes": [octo] },
+                        "comments": { "totalCount": 5 }
+                    }
+                ]
+            }
+        })
+    }
+
+    fn rest_user(login: &str, id: u64, node_id: &str) -> Value {
+        let api_url = format!("https://api.github.com/users/{}", login);
+        json!({
+            "login": login,
+            "id": id,
+            "node_id": node_id,
+            "avatar_url": format!("https://avatars.githubusercontent.com/u/{}?v=4", id),
+            "gravatar_id": "",
+            "url": api_url,
+            "html_url": format!("https://github.com/{}", login),
+            "followers_url": format!("{}/followers", api_url),
+            "following_url": format!("{}/following{{/other_user}}", api_url),
+            "gists_url": format!("{}/gists{{/gist_id}}", api_url),
+            "starred_url": format!("{}/starred{{/owner}}{{/repo}}", api_url),
+            "subscriptions_url": format!("{}/subscriptions", api_url),
+            "organizations_url": format!("{}/orgs", api_url),
+            "repos_url": format!("{}/repos", api_url),
+            "events_url": format!("{}/events{{/privacy}}", api_url),
+            "received_events_url": format!("{}/received_events", api_url),
+            "type": "User",
+            "user_view_type": "public",
+            "site_admin": false
+        })
+    }
+
+    /// The same results as returned by the REST search API.
+    fn rest_items() -> Vec<Value> {
+        let repo = "https://api.github.com/repos/guardian/frontend";
+        vec![
+            json!({
+                "url": format!("{}/issues/42", repo),
+                "repository_url": repo,
+                "labels_url": format!("{}/issues/42/labels{{/name}}", repo),
+                "comments_url": format!("{}/issues/42/comments", repo),
+                "events_url": format!("{}/issues/42/events", repo),
+                "html_url": "https://github.com/guardian/frontend/pull/42",
+                "id": 1001,
+                "node_id": "PR_kwDOAAABcs5",
+                "number": 42,
+                "title": "Add charts",
+                "user": rest_user("octo", 1, "MDQ6VXNlcjE="),
+                "labels": [{
+                    "id": 208045946,
+                    "node_id": "LA_kwDOAAABcs8",
+                    "url": format!("{}/labels/good%20first%20issue", repo),
+                    "name": "good first issue",
+                    "color": "7057ff",
+                    "default": true,
+                    "description": "Good for newcomers"
+                }],
+                "state": "closed",
+                "locked": false,
+                "assignee": null,
+                "assignees": [],
+                "milestone": null,
+                "comments": 2,
+                "created_at": "2026-04-01T10:00:00Z",
+                "updated_at": "2026-04-03T09:00:00Z",
+                "closed_at": "2026-04-02T10:00:00Z",
+                "author_association": "MEMBER",
+                "draft": false,
+                "pull_request": {
+                    "url": format!("{}/pulls/42", repo),
+                    "html_url": "https://github.com/guardian/frontend/pull/42",
+                    "diff_url": "https://github.com/guardian/frontend/pull/42.diff",
+                    "patch_url": "https://github.com/guardian/frontend/pull/42.patch",
+                    "merged_at": "2026-04-02T10:00:00Z"
+                },
+                "body": "Adds **charts**",
+                "state_reason": null,
+                "score": 1.0
+            }),
+            json!({
+                "url": format!("{}/issues/7", repo),
+                "repository_url": repo,
+                "labels_url": format!("{}/issues/7/labels{{/name}}", repo),
+                "comments_url": format!("{}/issues/7/comments", repo),
+                "events_url": format!("{}/issues/7/events", repo),
+                "html_url": "https://github.com/guardian/frontend/issues/7",
+                "id": 2002,
+                "node_id": "I_kwDOAAABcs6",
+                "number": 7,
+                "title": "Charts are blurry",
+                "user": rest_user(GHOST_USER_LOGIN, GHOST_USER_ID, GHOST_USER_NODE_ID),
+                "labels": [],
+                "state": "closed",
+                "locked": false,
+                "assignee": rest_user("octo", 1, "MDQ6VXNlcjE="),
+                "assignees": [rest_user("octo", 1, "MDQ6VXNlcjE=")],
+                "milestone": null,
+                "comments": 5,
+                "created_at": "2026-03-01T08:30:00Z",
+                "updated_at": "2026-03-05T12:00:00Z",
+                "closed_at": "2026-03-05T12:00:00Z",
+                "author_association": "NONE",
+                "body": "",
+                "state_reason": "completed",
+                "score": 1.0
+            }),
+        ]
+    }
+
+    fn graphql_items(category: SearchCategory) -> Vec<GithubSearchResponseItem> {
+        let data: SearchData = serde_json::from_value(graphql_response()).unwrap();
+        data.search
+            .nodes
+            .into_iter()
+            .flatten()
+            .map(|node| search_item(node, category, "octo", &github_com()))
+            .collect()
+    }
+
+    #[test]
+    fn search_items_match_the_rest_search_results() {
+        let graphql: Vec<Value> = graphql_items(SearchCategory::IssuesAssignedToMe)
+            .iter()
+            .map(|item| serde_json::to_value(item).unwrap())
+            .collect();
+
+        // Only the fields the GraphQL API doesn't return are left out
+        let rest: Vec<Value> = rest_items()
+            .into_iter()
+            .map(|item| {
+                let item: GithubSearchResponseItem = serde_json::from_value(item).unwrap();
+                let mut item = serde_json::to_value(item).unwrap();
+                let item_fields = item.as_object_mut().unwrap();
+                item_fields.remove("milestone");
+                item_fields.remove("score");
+                for label in item_fields["labels"].as_array_mut().unwrap() {
+                    label.as_object_mut().unwrap().remove("id");
+                }
+                item
+            })
+            .collect();
+
+        assert_eq!(graphql, rest);
+    }
+
+    #[test]
+    fn search_items_have_the_details_of_their_category() {
+        let authored = graphql_items(SearchCategory::AuthoredByMe);
+        let stats = authored[0].pr_stats.as_ref().unwrap();
+        assert_eq!(
+            (
+                stats.additions,
+                stats.deletions,
+                stats.changed_files,
+                stats.commits
+            ),
+            (120, 7, 4, 3)
+        );
+        // The author's own comments aren't a review
+        assert_eq!(
+            stats.first_review_at,
+            Some("2026-04-01T16:00:00Z".parse().unwrap())
+        );
+        assert!(authored[0].review_activity.is_none());
+        // Issues have no PR details
+        assert!(authored[1].pr_stats.is_none());
+
+        let reviewed = graphql_items(SearchCategory::ReviewedByMe);
+        let activity = reviewed[0].review_activity.as_ref().unwrap();
+        assert_eq!(activity.states, vec!["COMMENTED"]);
+        assert_eq!(
+            activity.first_response_at,
+            Some("2026-04-01T11:00:00Z".parse().unwrap())
+        );
+        assert!(reviewed[0].pr_stats.is_none());
+    }
+}
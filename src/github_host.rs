@@ -0,0 +1,122 @@
+use anyhow::{bail, Context};
+use octocrab::Octocrab;
+use url::Url;
+
+use crate::config::{get_profile_setting, profile};
+
+pub const DEFAULT_GITHUB_API_URL: &str = "https://api.github.com";
+pub const DEFAULT_GITHUB_URL: &str = "https://github.com";
+
+/// Where GitHub lives: github.com, or a GitHub Enterprise Server instance.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GithubHost {
+    /// Base URL of the REST API, e.g. `https://github.example.com/api/v3`, without a trailing slash.
+    pub api_url: String,
+    /// Base URL of the website, e.g. `https://github.example.com`, without a trailing slash.
+    pub web_url: String,
+}
+
+impl GithubHost {
+    /// Work out where GitHub lives from the `GITHUB_API_URL` and `GITHUB_URL` settings.
+    /// When only one of them is set, the other is derived from it the way
+    /// GitHub Enterprise Server lays out its URLs, with the API under `/api/v3`.
+    pub fn from_settings() -> anyhow::Result<Self> {
+        Self::from_profile_settings(profile())
+    }
+
+    /// Work out where GitHub lives for a profile.
+    pub fn from_profile_settings(profile: Option<&str>) -> anyhow::Result<Self> {
+        let api_url = get_profile_setting("GITHUB_API_URL", profile)
+            .map(|url| parse_base_url("GITHUB_API_URL", &url))
+            .transpose()?;
+        let web_url = get_profile_setting("GITHUB_URL", profile)
+            .map(|url| parse_base_url("GITHUB_URL", &url))
+            .transpose()?;
+
+        Ok(match (api_url, web_url) {
+            (Some(api_url), Some(web_url)) => GithubHost { api_url, web_url },
+            (Some(api_url), None) => GithubHost {
+                web_url: web_url_of(&api_url),
+                api_url,
+            },
+            (None, Some(web_url)) => GithubHost {
+                api_url: match web_url.as_str() {
+                    DEFAULT_GITHUB_URL => DEFAULT_GITHUB_API_URL.to_string(),
+                    _ => format!("{}/api/v3", web_url),
+                },
+                web_url,
+            },
+            (None, None) => GithubHost {
+                api_url: DEFAULT_GITHUB_API_URL.to_string(),
+                web_url: DEFAULT_GITHUB_URL.to_string(),
+            },
+        })
+    }
+
+    pub fn is_github_com(&self) -> bool {
+        self.api_url == DEFAULT_GITHUB_API_URL
+    }
+
+    /// The GraphQL endpoint, which GitHub Enterprise Server serves next to the REST API
+    /// (`/api/graphql`) rather than under it.
+    pub fn graphql_url(&self) -> String {
+        match self.api_url.strip_suffix("/v3") {
+            Some(api_root) => format!("{}/graphql", api_root),
+            None => format!("{}/graphql", self.api_url),
+        }
+    }
+
+    /// Where personal access tokens are created and managed.
+    pub fn tokens_url(&self) -> String {
+        format!("{}/settings/tokens", self.web_url)
+    }
+
+    /// A client for the REST API, authenticated with `token`.
+    pub fn octocrab(&self, token: &str) -> anyhow::Result<Octocrab> {
+        build_octocrab(&self.api_url, token)
+    }
+
+    /// A client for the GraphQL API, authenticated with `token`. Octocrab puts every request
+    /// under the path of its base URL, so the REST client can't reach `/api/graphql` on
+    /// GitHub Enterprise Server. This one is based at the root of the host instead.
+    pub fn graphql_octocrab(&self, token: &str) -> anyhow::Result<Octocrab> {
+        let mut root = Url::parse(&self.graphql_url())?;
+        root.set_path("");
+        build_octocrab(root.as_str(), token)
+    }
+}
+
+fn build_octocrab(base_url: &str, token: &str) -> anyhow::Result<Octocrab> {
+    Octocrab::builder()
+        .base_uri(base_url)
+        .with_context(|| format!("Invalid GitHub URL {}", base_url))?
+        .personal_token(token.to_string())
+        .build()
+        .context("Couldn't set up the GitHub client")
+}
+
+/// Check that a URL setting is an http(s) URL and strip any trailing slash.
+fn parse_base_url(setting: &str, url: &str) -> anyhow::Result<String> {
+    let parsed = Url::parse(url)
+        .with_context(|| format!("The {} setting \"{}\" isn't a valid URL", setting, url))?;
+    if !matches!(parsed.scheme(), "http" | "https") || parsed.host_str().is_none() {
+        bail!(
+            "The {} setting \"{}\" should be an http or https URL",
+            setting,
+            url
+        );
+    }
+    Ok(url.trim_end_matches('/').to_string())
+}
+
+/// The website of an API URL: github.com for `api.github.com`, or the same host without
+/// the `/api/v3` path for GitHub Enterprise Server.
+fn web_url_of(api_url: &str) -> String {
+    if api_url == DEFAULT_GITHUB_API_URL {
+        return DEFAULT_GITHUB_URL.to_string();
+    }
+    api_url
+        .strip_suffix("/api/v3")
+        .unwrap_or(api_url)
+        .to_string()
+}
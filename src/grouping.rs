@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+
+use chrono::Datelike;
+
+use crate::cli::GroupBy;
+use crate::github::repo_full_name;
+use crate::models::GithubSearchResponseItem;
+
+/// Name of the group of PRs and issues without any labels.
+const NO_LABEL: &str = "No label";
+
+/// PRs or issues sharing a repository, month, quarter or label.
+pub struct ItemGroup<'a> {
+    /// Name to show, e.g. "guardian/frontend", "April 2026", "Q2 2026" or a label.
+    pub name: String,
+    /// What the group is compared and ordered by, e.g. "2026-04" for April 2026.
+    key: String,
+    pub items: Vec<&'a GithubSearchResponseItem>,
+}
+
+/// Split PRs or issues into groups, keeping their order within each group.
+/// Months and quarters are ordered newest first, and repositories and labels largest first,
+/// with PRs and issues without labels last. Items with several labels are in each of their groups.
+pub fn group_items(items: &[GithubSearchResponseItem], by: GroupBy) -> Vec<ItemGroup<'_>> {
+    let mut groups: Vec<ItemGroup> = vec![];
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    for item in items {
+        for (key, name) in group_keys(item, by) {
+            let position = *positions.entry(key.to_string()).or_insert_with(|| {
+                groups.push(ItemGroup {
+                    name,
+                    key,
+                    items: vec![],
+                });
+                groups.len() - 1
+            });
+            groups[position].items.push(item);
+        }
+    }
+
+    match by {
+        GroupBy::Month | GroupBy::Quarter => groups.sort_by(|a, b| b.key.cmp(&a.key)),
+        GroupBy::Repo | GroupBy::Label => groups.sort_by(|a, b| {
+            (a.key.is_empty(), std::cmp::Reverse(a.items.len()), &a.key).cmp(&(
+                b.key.is_empty(),
+                std::cmp::Reverse(b.items.len()),
+                &b.key,
+            ))
+        }),
+    }
+    groups
+}
+
+/// The keys and names of the groups an item belongs to. Names are compared regardless of case,
+/// and items without labels have an empty key.
+fn group_keys(item: &GithubSearchResponseItem, by: GroupBy) -> Vec<(String, String)> {
+    match by {
+        GroupBy::Repo => {
+            let name = repo_full_name(&item.repository_url)
+                .unwrap_or_else(|| item.repository_url.to_string());
+            vec![(name.to_lowercase(), name)]
+        }
+        GroupBy::Month => vec![(
+            item.created_at.format("%Y-%m").to_string(),
+            item.created_at.format("%B %Y").to_string(),
+        )],
+        GroupBy::Quarter => {
+            let (year, quarter) = (item.created_at.year(), item.created_at.month0() / 3 + 1);
+            vec![(
+                format!("{}-Q{}", year, quarter),
+                format!("Q{} {}", quarter, year),
+            )]
+        }
+        GroupBy::Label if item.labels.is_empty() => vec![(String::new(), NO_LABEL.to_string())],
+        GroupBy::Label => {
+            let mut labels: Vec<(String, String)> = item
+                .labels
+                .iter()
+                .map(|l| (l.name.to_lowercase(), l.name.to_string()))
+                .collect();
+            // Labels that only differ in case are the same group, so the item is only listed once
+            labels.sort();
+            labels.dedup_by(|a, b| a.0 == b.0);
+            labels
+        }
+    }
+}
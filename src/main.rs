@@ -1,59 +1,88 @@
+pub mod cache;
+pub mod charts;
 pub mod cli;
+pub mod config;
 pub mod credentials;
+pub mod dates;
+pub mod doctor;
 pub mod generate_report;
 pub mod github;
+pub mod github_client;
+pub mod github_graphql;
+pub mod github_host;
+pub mod grouping;
+pub mod metrics;
 pub mod models;
+pub mod profile;
+pub mod reviews;
+pub mod summary;
 pub mod trello;
 
 use crate::generate_report::generate_report;
 use clap::StructOpt;
-use cli::{Args, Commands};
-use credentials::set_credentials;
-use ini::Ini;
+use cli::{Args, AuthType, Commands, ProfileCommands};
+use config::{github_orgs, set_profile};
+use credentials::{logout, migrate_credentials, read_secret_from_stdin, set_credentials};
+use doctor::{
+    doctor, print_github_check, print_trello_check, verify_github_token, verify_trello_credentials,
+};
+use github_host::GithubHost;
+use profile::{delete_profile, print_profiles};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let args = Args::parse();
-
-    let credential_store_path = format!("{}/.selfassessment", shellexpand::tilde("~/"));
-    let mut credential_store = match Ini::load_from_file(&credential_store_path) {
-        Ok(ini) => ini,
-        Err(_) => Ini::new(),
-    };
+    set_profile(args.profile)?;
 
     match args.command {
-        Commands::Auth { token } => {
-            set_credentials(
-                &mut credential_store,
-                credential_store_path,
-                "GitHub".to_owned(),
-                "GITHUB_TOKEN".to_owned(),
-                token,
-            )?;
-        }
-        Commands::TrelloAuth { key, token } => {
-            set_credentials(
-                &mut credential_store,
-                credential_store_path.clone(),
-                "Trello".to_owned(),
-                "TRELLO_KEY".to_owned(),
-                key,
-            )?;
-
-            set_credentials(
-                &mut credential_store,
-                credential_store_path,
-                "Trello".to_owned(),
-                "TRELLO_TOKEN".to_owned(),
-                token,
-            )?;
+        Commands::Auth {
+            token,
+            token_stdin,
+            no_verify,
+        } => {
+            let token = match token {
+                Some(token) if !token_stdin => token,
+                _ => read_secret_from_stdin(AuthType::GitHubAuthToken)?.value,
+            };
+            if !no_verify {
+                let host = GithubHost::from_settings()?;
+                let check = verify_github_token(&host, &token, &github_orgs(vec![])).await?;
+                print_github_check(&check);
+            }
+            set_credentials(AuthType::GitHubAuthToken, token)?;
         }
-        Commands::GenerateReport {
-            from,
-            to,
-            skip_trello,
+        Commands::TrelloAuth {
+            key,
+            token,
+            token_stdin,
+            no_verify,
         } => {
-            generate_report(from, to, skip_trello).await?;
+            let token = match token {
+                Some(token) if !token_stdin => token,
+                _ => read_secret_from_stdin(AuthType::TrelloServerToken)?.value,
+            };
+            if !no_verify {
+                let check = verify_trello_credentials(&key, &token).await?;
+                print_trello_check(&check);
+            }
+            set_credentials(AuthType::TrelloApiKey, key)?;
+            set_credentials(AuthType::TrelloServerToken, token)?;
+        }
+        Commands::Doctor => {
+            doctor().await?;
+        }
+        Commands::MigrateCredentials => {
+            migrate_credentials()?;
+        }
+        Commands::Logout { service, revoke } => {
+            logout(service, revoke).await?;
+        }
+        Commands::Profile { command } => match command {
+            ProfileCommands::List => print_profiles()?,
+            ProfileCommands::Delete { name } => delete_profile(&name)?,
+        },
+        Commands::GenerateReport(options) => {
+            generate_report(options).await?;
         }
     }
 
@@ -0,0 +1,158 @@
+use chrono::Duration;
+
+use crate::cache::Cache;
+use crate::dates::describe_duration;
+use crate::github::{merged_at, repo_full_name};
+use crate::github_client::GithubClient;
+use crate::models::{
+    GithubSearchResponseItem, PullRequestDetails, PullRequestReview, PullRequestStats,
+    SearchCategory, TemplateMetric, TemplatePrStats,
+};
+
+/// Fetch the size and review timing of each authored PR that doesn't have them yet.
+/// They are saved to the cache along with the PR, so they are only fetched again
+/// once the PR changes.
+pub async fn fetch_pr_stats(
+    client: &GithubClient,
+    prs: &mut [GithubSearchResponseItem],
+    cache: Option<&Cache>,
+) -> anyhow::Result<()> {
+    let missing: Vec<&mut GithubSearchResponseItem> =
+        prs.iter_mut().filter(|pr| pr.pr_stats.is_none()).collect();
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    println!(
+        "[self-assessment] 📏 Fetching the size and reviews of {} PRs...",
+        missing.len()
+    );
+    for pr in missing {
+        let Some(repo) = repo_full_name(&pr.repository_url) else {
+            continue;
+        };
+        // A single PR shouldn't stop the report, it's just left out of the metrics
+        match pr_stats(client, &repo, pr.number, &pr.user.login).await {
+            Ok(stats) => {
+                pr.pr_stats = Some(stats);
+                if let Some(cache) = cache {
+                    cache.update_github_item(SearchCategory::AuthoredByMe.cache_key(), pr)?;
+                }
+            }
+            Err(err) => eprintln!(
+                "[self-assessment] ⚠️  Couldn't fetch the details of {}: {:#}",
+                pr.html_url, err
+            ),
+        }
+    }
+
+    Ok(())
+}
+
+async fn pr_stats(
+    client: &GithubClient,
+    repo: &str,
+    number: u32,
+    author: &str,
+) -> anyhow::Result<PullRequestStats> {
+    let route = format!("/repos/{}/pulls/{}", repo, number);
+    let details: PullRequestDetails = client.get(&route, &Default::default()).await?;
+    let reviews: Vec<PullRequestReview> =
+        client.get_all_pages(&format!("{}/reviews", route)).await?;
+
+    Ok(PullRequestStats {
+        additions: details.additions,
+        deletions: details.deletions,
+        changed_files: details.changed_files,
+        commits: details.commits,
+        // Authors can leave comments as a review of their own PR, which isn't a review
+        first_review_at: reviews
+            .iter()
+            .filter(|r| {
+                r.user
+                    .as_ref()
+                    .is_some_and(|u| !u.login.eq_ignore_ascii_case(author))
+            })
+            .filter_map(|r| r.submitted_at)
+            .min(),
+    })
+}
+
+fn time_to_first_review(pr: &GithubSearchResponseItem) -> Option<Duration> {
+    let first_review_at = pr.pr_stats.as_ref()?.first_review_at?;
+    Some(first_review_at - pr.created_at)
+}
+
+fn time_to_merge(pr: &GithubSearchResponseItem) -> Option<Duration> {
+    merged_at(pr).map(|merged_at| merged_at - pr.created_at)
+}
+
+/// Format the size and timing of an authored PR for the report template.
+pub fn format_pr_stats(pr: &GithubSearchResponseItem) -> Option<TemplatePrStats> {
+    let stats = pr.pr_stats.as_ref()?;
+    Some(TemplatePrStats {
+        additions: stats.additions,
+        deletions: stats.deletions,
+        changed_files: stats.changed_files,
+        commits: stats.commits,
+        time_to_first_review: time_to_first_review(pr).map(describe_duration),
+        time_to_merge: time_to_merge(pr).map(describe_duration),
+    })
+}
+
+/// The median, 75th and 90th percentiles of each metric across the authored PRs.
+/// Metrics that couldn't be measured for any PR are left out.
+pub fn pr_metrics(prs: &[GithubSearchResponseItem]) -> Vec<TemplateMetric> {
+    let with_stats: Vec<(&GithubSearchResponseItem, &PullRequestStats)> = prs
+        .iter()
+        .filter_map(|pr| pr.pr_stats.as_ref().map(|stats| (pr, stats)))
+        .collect();
+    let count = |f: fn(&PullRequestStats) -> u32| -> Vec<f64> {
+        with_stats.iter().map(|(_, s)| f(s) as f64).collect()
+    };
+    let seconds = |f: fn(&GithubSearchResponseItem) -> Option<Duration>| -> Vec<f64> {
+        with_stats
+            .iter()
+            .filter_map(|(pr, _)| f(pr))
+            .map(|d| d.num_seconds() as f64)
+            .collect()
+    };
+    let as_count = |v: f64| format!("{:.0}", v);
+    let as_duration = |v: f64| describe_duration(Duration::seconds(v as i64));
+
+    [
+        (
+            "Lines changed",
+            count(|s| s.additions + s.deletions),
+            as_count as fn(f64) -> String,
+        ),
+        ("Files changed", count(|s| s.changed_files), as_count),
+        ("Commits", count(|s| s.commits), as_count),
+        (
+            "Time to first review",
+            seconds(time_to_first_review),
+            as_duration,
+        ),
+        ("Time to merge", seconds(time_to_merge), as_duration),
+    ]
+    .into_iter()
+    .filter(|(_, values, _)| !values.is_empty())
+    .map(|(name, mut values, format)| {
+        values.sort_by(f64::total_cmp);
+        TemplateMetric {
+            name,
+            count: values.len(),
+            median: format(percentile(&values, 50.0)),
+            p75: format(percentile(&values, 75.0)),
+            p90: format(percentile(&values, 90.0)),
+        }
+    })
+    .collect()
+}
+
+/// The `p`th percentile of sorted values, interpolating between the closest two.
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let rank = p / 100.0 * (sorted.len() - 1) as f64;
+    let (lower, upper) = (rank.floor() as usize, rank.ceil() as usize);
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f64)
+}
@@ -1,18 +1,40 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::fmt::Display;
 
-#[derive(Debug)]
-pub enum GuardianPullRequests {
+use crate::dates::DateRange;
+use std::{collections::HashMap, fmt::Display};
+
+/// A kind of GitHub activity, each found with its own issue search and shown in its own section.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchCategory {
     AuthoredByMe,
     ReviewedByMe,
+    IssuesOpenedByMe,
+    IssuesCommentedOnByMe,
+    IssuesAssignedToMe,
+}
+
+impl SearchCategory {
+    /// Name of the category in the cache and in CSV exports.
+    pub fn cache_key(&self) -> &'static str {
+        match &self {
+            SearchCategory::AuthoredByMe => "authored",
+            SearchCategory::ReviewedByMe => "reviewed",
+            SearchCategory::IssuesOpenedByMe => "issues-opened",
+            SearchCategory::IssuesCommentedOnByMe => "issues-commented",
+            SearchCategory::IssuesAssignedToMe => "issues-assigned",
+        }
+    }
 }
 
-impl Display for GuardianPullRequests {
+impl Display for SearchCategory {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match &self {
-            GuardianPullRequests::AuthoredByMe => write!(f, "pull requests authored by you"),
-            GuardianPullRequests::ReviewedByMe => write!(f, "pull requests reviewed by you"),
+            SearchCategory::AuthoredByMe => write!(f, "pull requests authored by you"),
+            SearchCategory::ReviewedByMe => write!(f, "pull requests reviewed by you"),
+            SearchCategory::IssuesOpenedByMe => write!(f, "issues opened by you"),
+            SearchCategory::IssuesCommentedOnByMe => write!(f, "issues commented on by you"),
+            SearchCategory::IssuesAssignedToMe => write!(f, "issues assigned to you"),
         }
     }
 }
@@ -24,6 +46,14 @@ pub struct GithubSearchResponse {
     pub items: Vec<GithubSearchResponseItem>,
 }
 
+/// Search results merged across organisations, date windows and pages.
+#[derive(Debug, Default)]
+pub struct SearchResults {
+    pub items: Vec<GithubSearchResponseItem>,
+    /// Set when GitHub couldn't return every match, even after retrying.
+    pub incomplete: bool,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GithubSearchResponseItem {
     pub url: String,
@@ -40,16 +70,65 @@ pub struct GithubSearchResponseItem {
     pub labels: Vec<Label>,
     pub state: String,
     pub assignee: Option<User>,
-    pub milestone: Option<Milestone>,
+    /// Left out when fetched with the GraphQL API, which doesn't return it,
+    /// so that it isn't mistaken for an item without a milestone.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_present",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub milestone: Option<Option<Milestone>>,
     pub comments: u32,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub closed_at: Option<DateTime<Utc>>,
-    pub pull_request: PullRequest,
+    /// Only set for pull requests, since the issue search returns both.
+    pub pull_request: Option<PullRequest>,
     pub body: Option<String>,
-    pub score: f32,
+    /// Search relevance. Left out when fetched with the GraphQL API, which doesn't return it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub score: Option<f32>,
     pub locked: bool,
     pub author_association: String,
+    /// Only set for PRs reviewed by the user, once their reviews have been fetched.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub review_activity: Option<ReviewActivity>,
+    /// Only set for PRs authored by the user, once their details have been fetched.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pr_stats: Option<PullRequestStats>,
+}
+
+/// Deserialize a field that may be `null`, telling it apart from a missing one:
+/// a missing field is `None`, and `null` is `Some(None)`.
+fn deserialize_present<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// Size and review timing of a PR authored by the user.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PullRequestStats {
+    pub additions: u32,
+    pub deletions: u32,
+    pub changed_files: u32,
+    pub commits: u32,
+    /// When someone other than the author first submitted a review.
+    pub first_review_at: Option<DateTime<Utc>>,
+}
+
+/// The user's part in reviewing a PR, worked out from its reviews and review comments.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReviewActivity {
+    /// States of the user's reviews, oldest first:
+    /// `APPROVED`, `CHANGES_REQUESTED`, `COMMENTED` or `DISMISSED`.
+    pub states: Vec<String>,
+    /// Number of comments the user left on lines of the diff.
+    pub inline_comments: u32,
+    /// When the user first submitted a review or commented on the diff.
+    pub first_response_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -61,6 +140,37 @@ pub struct PullRequest {
     pub merged_at: Option<DateTime<Utc>>,
 }
 
+/// The diff statistics of a PR from `/repos/{owner}/{repo}/pulls/{number}`.
+#[derive(Debug, Deserialize)]
+pub struct PullRequestDetails {
+    pub additions: u32,
+    pub deletions: u32,
+    pub changed_files: u32,
+    pub commits: u32,
+}
+
+/// A review from `/repos/{owner}/{repo}/pulls/{number}/reviews`.
+#[derive(Debug, Deserialize)]
+pub struct PullRequestReview {
+    /// `null` when the reviewer's account was deleted.
+    pub user: Option<ReviewAuthor>,
+    pub state: String,
+    /// `null` for reviews that are still pending.
+    pub submitted_at: Option<DateTime<Utc>>,
+}
+
+/// A comment on the diff from `/repos/{owner}/{repo}/pulls/{number}/comments`.
+#[derive(Debug, Deserialize)]
+pub struct PullRequestReviewComment {
+    pub user: Option<ReviewAuthor>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReviewAuthor {
+    pub login: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct User {
     pub login: String,
@@ -84,7 +194,9 @@ pub struct User {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Label {
-    pub id: u64,
+    /// Left out when fetched with the GraphQL API, which doesn't return it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
     pub node_id: String,
     pub url: String,
     pub name: String,
@@ -111,6 +223,60 @@ pub struct Milestone {
     pub due_on: Option<DateTime<Utc>>,
 }
 
+/// Data passed to report templates, including the custom ones given with `--template`.
+/// Custom templates rely on these field names, so fields should only ever be added.
+#[derive(Serialize)]
+pub struct TemplateContext<'a> {
+    /// GitHub login of the user the report is about.
+    pub github_user: &'a str,
+    /// Start of the date range, ready to display, e.g. "From 2024-01-01".
+    pub start_date: String,
+    /// End of the date range, ready to display, e.g. "to 2024-06-30".
+    pub end_date: String,
+    /// PRs authored by the user, newest first.
+    pub prs: Vec<TemplatePr>,
+    /// PRs reviewed by the user, newest first.
+    pub reviews: Vec<TemplatePr>,
+    pub prs_len: usize,
+    pub reviews_len: usize,
+    /// How PRs and issues are grouped with `--group-by`: repo, month, quarter or label.
+    /// `null` when they aren't grouped.
+    pub group_by: Option<&'static str>,
+    /// The same PRs and issues as in the lists, in groups.
+    pub prs_groups: Vec<TemplateGroup>,
+    pub reviews_groups: Vec<TemplateGroup>,
+    pub issues_opened_groups: Vec<TemplateGroup>,
+    pub issues_commented_groups: Vec<TemplateGroup>,
+    pub issues_assigned_groups: Vec<TemplateGroup>,
+    /// Size and lifecycle metrics of the authored PRs. Empty when no PR details were fetched.
+    pub pr_metrics: Vec<TemplateMetric>,
+    /// Statistics about the PRs and issues, shown at the top of the report.
+    pub summary: Summary,
+    pub charts: TemplateCharts,
+    /// Issues opened by the user, newest first.
+    pub issues_opened: Vec<TemplatePr>,
+    /// Issues opened by others that the user commented on, newest first.
+    pub issues_commented: Vec<TemplatePr>,
+    /// Issues opened by others that are assigned to the user, newest first.
+    pub issues_assigned: Vec<TemplatePr>,
+    pub issues_opened_len: usize,
+    pub issues_commented_len: usize,
+    pub issues_assigned_len: usize,
+    /// Set when GitHub couldn't return every PR or issue, so the report may be missing some.
+    pub partial_data: bool,
+    /// Whether the report includes Trello cards.
+    pub display_trello: bool,
+    /// Trello boards and the cards assigned to the user. `null` without Trello.
+    pub trello_boards: Option<Vec<BoardAndCards>>,
+    /// Number of Trello cards across all boards.
+    pub cards_len: usize,
+    /// The Trello user, with `id`, `fullName` and `avatarUrl` fields. `null` without Trello.
+    pub user: Option<&'a TrelloUser>,
+    /// The credentials used to fetch the data, each with a `credential` and the `source` it
+    /// was found in. Empty offline.
+    pub credentials: &'a [ReportCredential],
+}
+
 #[derive(Serialize)]
 pub struct TemplatePr {
     pub status: String,
@@ -118,12 +284,129 @@ pub struct TemplatePr {
     pub title: String,
     pub html_url: String,
     pub repo_name: String,
+    pub repo_url: String,
     pub comments: u32,
     pub comments_present: (bool, bool),
     pub body: String,
     pub labels: String,
     pub author: String,
     pub profile_pic: String,
+    /// How the user reviewed the PR. `null` for PRs they didn't review and for issues.
+    pub review: Option<TemplateReview>,
+    /// Size and timing of a PR the user authored. `null` for other PRs and for issues.
+    pub stats: Option<TemplatePrStats>,
+}
+
+#[derive(Serialize)]
+pub struct TemplatePrStats {
+    pub additions: u32,
+    pub deletions: u32,
+    pub changed_files: u32,
+    pub commits: u32,
+    /// How long the PR waited for its first review, e.g. "3 hours".
+    pub time_to_first_review: Option<String>,
+    /// How long the PR took to merge, e.g. "2 days".
+    pub time_to_merge: Option<String>,
+}
+
+/// Median and percentiles of a PR metric across the authored PRs.
+#[derive(Serialize)]
+pub struct TemplateMetric {
+    /// What is measured, e.g. "Time to merge".
+    pub name: &'static str,
+    /// Number of PRs the metric could be measured for.
+    pub count: usize,
+    pub median: String,
+    pub p75: String,
+    pub p90: String,
+}
+
+/// Statistics about the PRs and issues in a report, shown at its top and included in JSON exports.
+#[derive(Serialize)]
+pub struct Summary {
+    /// Authored PRs by state. Merged PRs aren't counted as closed.
+    pub merged: usize,
+    pub closed: usize,
+    pub open: usize,
+    /// Repositories with authored or reviewed PRs or issues, most active first.
+    pub repos: Vec<RepoSummary>,
+    /// Number of distinct repositories contributed to.
+    pub repos_len: usize,
+    /// Authored and reviewed PRs by the month they were opened, oldest first,
+    /// including months without any.
+    pub months: Vec<MonthSummary>,
+    /// The most used labels on authored PRs and opened issues, most used first.
+    pub top_labels: Vec<LabelCount>,
+    /// The weeks with the most authored PRs, reviews and opened issues, busiest first.
+    pub busiest_weeks: Vec<WeekSummary>,
+    /// Reviewed PRs per authored PR, rounded to two decimals. `null` without authored PRs.
+    pub review_ratio: Option<f64>,
+}
+
+#[derive(Serialize)]
+pub struct RepoSummary {
+    /// Full name of the repository, e.g. "guardian/frontend".
+    pub name: String,
+    pub url: String,
+    pub authored: usize,
+    pub reviewed: usize,
+    /// Issues opened, commented on or assigned.
+    pub issues: usize,
+    pub total: usize,
+}
+
+#[derive(Serialize)]
+pub struct MonthSummary {
+    /// The month in YYYY-MM format.
+    pub month: String,
+    pub authored: usize,
+    pub reviewed: usize,
+}
+
+#[derive(Serialize)]
+pub struct LabelCount {
+    pub name: String,
+    /// Hex colour of the label, without the leading `#`.
+    pub color: String,
+    pub count: usize,
+}
+
+#[derive(Serialize)]
+pub struct WeekSummary {
+    /// Monday of the week in YYYY-MM-DD format.
+    pub week_of: String,
+    pub count: usize,
+}
+
+/// PRs or issues in a group of the report, with `--group-by`.
+#[derive(Serialize)]
+pub struct TemplateGroup {
+    /// Name of the group, e.g. "guardian/frontend", "April 2026", "Q2 2026" or a label.
+    /// `null` when the report isn't grouped, so all PRs or issues are in a single group.
+    pub name: Option<String>,
+    pub count: usize,
+    pub items: Vec<TemplatePr>,
+}
+
+/// Inline SVG charts, only drawn for HTML reports. Each is `null` when there is nothing to chart.
+#[derive(Serialize, Default)]
+pub struct TemplateCharts {
+    /// PRs authored and reviewed each week.
+    pub timeline: Option<String>,
+    /// PRs authored and reviewed in each repository.
+    pub repos: Option<String>,
+    /// Trello cards in each board.
+    pub trello_boards: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct TemplateReview {
+    /// The user's review verdicts, e.g. "Commented, Approved".
+    pub verdicts: String,
+    pub inline_comments: u32,
+    pub inline_comments_present: (bool, bool),
+    /// How long after the PR was opened the user first responded, e.g. "3 hours".
+    pub first_response: Option<String>,
 }
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TrelloBoard {
@@ -140,9 +423,38 @@ pub struct TrelloCard {
     pub id_members: Vec<String>,
     pub url: String,
     pub date_last_activity: DateTime<Utc>,
+    pub due: Option<DateTime<Utc>>,
     pub labels: Vec<TrelloLabel>,
 }
 
+/// A card being created or moved between lists.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TrelloAction {
+    pub id: String,
+    pub date: DateTime<Utc>,
+    pub data: TrelloActionData,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrelloActionData {
+    pub card: Option<TrelloActionCard>,
+    /// The list a card was created in.
+    pub list: Option<TrelloActionList>,
+    /// The list a card was moved to.
+    pub list_after: Option<TrelloActionList>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TrelloActionCard {
+    pub id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TrelloActionList {
+    pub name: String,
+}
+
 #[derive(Serialize)]
 pub struct TemplateTrelloCard {
     pub name: String,
@@ -172,3 +484,146 @@ pub struct BoardAndCards {
     pub board: String,
     pub cards: Vec<TemplateTrelloCard>,
 }
+
+/// Everything collected for a report, before it is formatted for a particular output.
+pub struct ReportData {
+    pub github_user: octocrab::models::Author,
+    pub authored: SearchResults,
+    pub reviewed: SearchResults,
+    pub issues_opened: SearchResults,
+    pub issues_commented: SearchResults,
+    pub issues_assigned: SearchResults,
+    pub trello_user: Option<TrelloUser>,
+    /// Trello cards assigned to the user, keyed by board name.
+    pub trello_cards: Option<HashMap<String, Vec<TrelloCard>>>,
+    pub date_range: DateRange,
+    /// The credentials used to fetch the data and where each was found. Empty offline.
+    pub credentials: Vec<ReportCredential>,
+}
+
+/// A credential used to fetch the report data and where it was found.
+#[derive(Serialize)]
+pub struct ReportCredential {
+    /// e.g. "GitHub personal access token".
+    pub credential: &'static str,
+    /// e.g. "the GITHUB_TOKEN environment variable".
+    pub source: String,
+}
+
+impl ReportData {
+    /// Whether GitHub couldn't return every PR or issue in the report.
+    pub fn partial_data(&self) -> bool {
+        self.authored.incomplete
+            || self.reviewed.incomplete
+            || self.issues_opened.incomplete
+            || self.issues_commented.incomplete
+            || self.issues_assigned.incomplete
+    }
+}
+
+/// Version of the JSON export schema.
+/// Bump it whenever a field of the export is renamed, removed or changes meaning.
+pub const JSON_SCHEMA_VERSION: u32 = 1;
+
+/// Machine-readable export of the collected report data.
+#[derive(Serialize)]
+pub struct JsonReport<'a> {
+    pub schema_version: u32,
+    pub generated_at: DateTime<Utc>,
+    pub github_user: JsonGithubUser,
+    pub date_range: JsonDateRange,
+    /// Whether GitHub couldn't return every PR or issue in the report.
+    pub partial_data: bool,
+    pub authored: &'a [GithubSearchResponseItem],
+    pub reviewed: &'a [GithubSearchResponseItem],
+    /// Issues have a `null` `pull_request`.
+    pub issues_opened: &'a [GithubSearchResponseItem],
+    pub issues_commented: &'a [GithubSearchResponseItem],
+    pub issues_assigned: &'a [GithubSearchResponseItem],
+    pub summary: Summary,
+    /// `null` without `--group-by`.
+    pub groups: Option<JsonGroups>,
+    /// `null` when the Trello report was skipped or failed.
+    pub trello: Option<JsonTrello<'a>>,
+    /// The credentials used to fetch the data and where each was found. Empty offline.
+    pub credentials: &'a [ReportCredential],
+}
+
+/// PRs and issues grouped with `--group-by`, referring to them by their `html_url`.
+#[derive(Serialize)]
+pub struct JsonGroups {
+    /// How they are grouped: repo, month, quarter or label.
+    pub by: &'static str,
+    pub authored: Vec<JsonGroup>,
+    pub reviewed: Vec<JsonGroup>,
+    pub issues_opened: Vec<JsonGroup>,
+    pub issues_commented: Vec<JsonGroup>,
+    pub issues_assigned: Vec<JsonGroup>,
+}
+
+#[derive(Serialize)]
+pub struct JsonGroup {
+    pub name: String,
+    pub count: usize,
+    pub html_urls: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct JsonGithubUser {
+    pub login: String,
+    pub id: u64,
+    pub html_url: String,
+    pub avatar_url: String,
+}
+
+/// Inclusive date range in YYYY-MM-DD format. An open end is `null`.
+#[derive(Serialize)]
+pub struct JsonDateRange {
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct JsonTrello<'a> {
+    pub user: &'a TrelloUser,
+    /// Boards sorted by name, each with the cards assigned to the user.
+    pub boards: Vec<JsonTrelloBoard<'a>>,
+}
+
+#[derive(Serialize)]
+pub struct JsonTrelloBoard<'a> {
+    pub name: &'a str,
+    pub cards: &'a [TrelloCard],
+}
+
+/// One row of the PR and issue spreadsheet.
+#[derive(Serialize)]
+pub struct CsvPrRow<'a> {
+    pub kind: &'static str,
+    pub repo: String,
+    pub number: u32,
+    pub title: &'a str,
+    pub state: &'a str,
+    pub merged: bool,
+    pub created_at: String,
+    pub closed_at: Option<String>,
+    pub merged_at: Option<String>,
+    pub comments: u32,
+    pub labels: String,
+    pub url: &'a str,
+    /// Diff statistics, only filled in for authored PRs.
+    pub additions: Option<u32>,
+    pub deletions: Option<u32>,
+    pub changed_files: Option<u32>,
+    pub commits: Option<u32>,
+}
+
+/// One row of the Trello card spreadsheet.
+#[derive(Serialize)]
+pub struct CsvTrelloCardRow<'a> {
+    pub board: &'a str,
+    pub card: &'a str,
+    pub labels: String,
+    pub last_activity: String,
+    pub url: &'a str,
+}
@@ -0,0 +1,56 @@
+use anyhow::bail;
+
+use crate::cache::Cache;
+use crate::cli::AuthType;
+use crate::config::{
+    delete_profile_sections, get_profile_setting, list_profiles, DEFAULT_GITHUB_ORG,
+};
+use crate::credentials::{credential_store_for, delete_credentials};
+use crate::github_host::GithubHost;
+
+/// Print every profile, with which credentials, GitHub host and organisations are set up in each.
+pub fn print_profiles() -> anyhow::Result<()> {
+    let profiles = std::iter::once(None).chain(list_profiles().into_iter().map(Some));
+
+    for profile in profiles {
+        let store = credential_store_for(profile.as_deref())?;
+        let github = store.get(AuthType::GitHubAuthToken)?.is_some();
+        let trello = store.get(AuthType::TrelloApiKey)?.is_some()
+            && store.get(AuthType::TrelloServerToken)?.is_some();
+
+        let host = match GithubHost::from_profile_settings(profile.as_deref()) {
+            Ok(host) if host.is_github_com() => String::new(),
+            Ok(host) => format!(" at {}", host.web_url),
+            Err(err) => format!(" ({:#})", err),
+        };
+
+        println!(
+            "[self-assessment] 👤 {}: GitHub{} {}, Trello {}, organisations: {}",
+            profile.as_deref().unwrap_or("default"),
+            host,
+            if github { "✅" } else { "❌" },
+            if trello { "✅" } else { "❌" },
+            get_profile_setting("ORGS", profile.as_deref())
+                .unwrap_or_else(|| DEFAULT_GITHUB_ORG.to_string())
+        );
+    }
+
+    Ok(())
+}
+
+/// Delete a named profile, along with its credentials, settings and cache.
+pub fn delete_profile(name: &str) -> anyhow::Result<()> {
+    if !list_profiles().iter().any(|p| p == name) {
+        bail!("There is no profile called \"{}\"", name);
+    }
+
+    delete_credentials(Some(name))?;
+    delete_profile_sections(name)?;
+    let cache = Cache::path_for(Some(name))?;
+    if cache.exists() {
+        std::fs::remove_file(&cache)?;
+    }
+
+    println!("[self-assessment] 🗑️ Deleted the {} profile.", name);
+    Ok(())
+}
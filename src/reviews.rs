@@ -0,0 +1,133 @@
+use crate::cache::Cache;
+use crate::cli::ReportFormat;
+use crate::dates::describe_duration;
+use crate::github::repo_full_name;
+use crate::github_client::GithubClient;
+use crate::models::{
+    GithubSearchResponseItem, PullRequestReview, PullRequestReviewComment, ReviewActivity,
+    ReviewAuthor, SearchCategory, TemplateReview,
+};
+
+/// Fetch how the user reviewed each PR that doesn't have its review activity yet.
+/// The activity is saved to the cache along with the PR, so it is only fetched again
+/// once the PR changes.
+pub async fn fetch_review_activity(
+    client: &GithubClient,
+    login: &str,
+    prs: &mut [GithubSearchResponseItem],
+    cache: Option<&Cache>,
+) -> anyhow::Result<()> {
+    let missing: Vec<&mut GithubSearchResponseItem> = prs
+        .iter_mut()
+        .filter(|pr| pr.review_activity.is_none())
+        .collect();
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    println!(
+        "[self-assessment] 🧐 Fetching your reviews of {} PRs...",
+        missing.len()
+    );
+    for pr in missing {
+        let Some(repo) = repo_full_name(&pr.repository_url) else {
+            continue;
+        };
+        // A single PR shouldn't stop the report, it's just shown without its reviews
+        match review_activity(client, login, &repo, pr.number).await {
+            Ok(activity) => {
+                pr.review_activity = Some(activity);
+                if let Some(cache) = cache {
+                    cache.update_github_item(SearchCategory::ReviewedByMe.cache_key(), pr)?;
+                }
+            }
+            Err(err) => eprintln!(
+                "[self-assessment] ⚠️  Couldn't fetch your reviews of {}: {:#}",
+                pr.html_url, err
+            ),
+        }
+    }
+
+    Ok(())
+}
+
+/// Work out the user's review activity on a PR from its reviews and review comments.
+async fn review_activity(
+    client: &GithubClient,
+    login: &str,
+    repo: &str,
+    number: u32,
+) -> anyhow::Result<ReviewActivity> {
+    let reviews: Vec<PullRequestReview> = client
+        .get_all_pages(&format!("/repos/{}/pulls/{}/reviews", repo, number))
+        .await?;
+    let comments: Vec<PullRequestReviewComment> = client
+        .get_all_pages(&format!("/repos/{}/pulls/{}/comments", repo, number))
+        .await?;
+
+    let is_mine = |user: &Option<ReviewAuthor>| {
+        user.as_ref()
+            .is_some_and(|u| u.login.eq_ignore_ascii_case(login))
+    };
+    // Pending reviews haven't been submitted yet, so they don't count
+    let my_reviews: Vec<&PullRequestReview> = reviews
+        .iter()
+        .filter(|r| is_mine(&r.user) && r.state != "PENDING")
+        .collect();
+    let my_comments: Vec<&PullRequestReviewComment> =
+        comments.iter().filter(|c| is_mine(&c.user)).collect();
+
+    Ok(ReviewActivity {
+        states: my_reviews.iter().map(|r| r.state.to_string()).collect(),
+        inline_comments: my_comments.len() as u32,
+        first_response_at: my_reviews
+            .iter()
+            .filter_map(|r| r.submitted_at)
+            .chain(my_comments.iter().map(|c| c.created_at))
+            .min(),
+    })
+}
+
+/// Format the user's review activity on a PR for the report template.
+pub fn format_review(
+    pr: &GithubSearchResponseItem,
+    format: ReportFormat,
+) -> Option<TemplateReview> {
+    let activity = pr
+        .review_activity
+        .as_ref()
+        .filter(|a| !a.states.is_empty())?;
+
+    // Reviewing several times in a row with the same verdict reads as a single verdict
+    let mut states: Vec<&str> = activity.states.iter().map(|s| s.as_str()).collect();
+    states.dedup();
+    let verdicts = states
+        .iter()
+        .map(|state| {
+            let (name, badge) = match *state {
+                "APPROVED" => ("Approved", "bg-success"),
+                "CHANGES_REQUESTED" => ("Changes requested", "bg-danger"),
+                "COMMENTED" => ("Commented", "bg-secondary"),
+                "DISMISSED" => ("Dismissed", "bg-light text-dark"),
+                other => (other, "bg-light text-dark"),
+            };
+            match format {
+                ReportFormat::Markdown => name.to_string(),
+                _ => format!("<span class=\"badge {}\">{}</span>", badge, name),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(match format {
+            ReportFormat::Markdown => ", ",
+            _ => " ",
+        });
+
+    Some(TemplateReview {
+        verdicts,
+        inline_comments: activity.inline_comments,
+        inline_comments_present: (activity.inline_comments > 0, activity.inline_comments == 1),
+        first_response: activity
+            .first_response_at
+            .map(|at| describe_duration(at - pr.created_at)),
+    })
+}
@@ -0,0 +1,335 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use chrono::{DateTime, Datelike, Days, Months, NaiveDate, Utc};
+
+use crate::github::{merged_at, repo_full_name, repo_html_url};
+use crate::models::{
+    GithubSearchResponseItem, LabelCount, MonthSummary, RepoSummary, ReportData, Summary,
+    WeekSummary,
+};
+
+/// How many of the most used labels to show.
+const TOP_LABELS: usize = 10;
+/// How many of the busiest weeks to show.
+const BUSIEST_WEEKS: usize = 3;
+
+/// Work out the statistics shown at the top of the report.
+pub fn summarise(report: &ReportData) -> Summary {
+    let authored = &report.authored.items;
+    let reviewed = &report.reviewed.items;
+    let issues: Vec<&GithubSearchResponseItem> = report
+        .issues_opened
+        .items
+        .iter()
+        .chain(&report.issues_commented.items)
+        .chain(&report.issues_assigned.items)
+        .collect();
+
+    let merged = authored.iter().filter(|pr| merged_at(pr).is_some()).count();
+    let open = authored.iter().filter(|pr| pr.state == "open").count();
+
+    let repos = repo_totals(authored, reviewed, &issues);
+
+    let activity: Vec<DateTime<Utc>> = authored
+        .iter()
+        .chain(&report.issues_opened.items)
+        .map(|item| item.created_at)
+        .chain(reviewed.iter().map(reviewed_at))
+        .collect();
+
+    Summary {
+        merged,
+        closed: authored.len() - merged - open,
+        open,
+        repos_len: repos.len(),
+        repos,
+        months: months(authored, reviewed),
+        top_labels: top_labels(authored.iter().chain(&report.issues_opened.items)),
+        busiest_weeks: busiest_weeks(&activity),
+        review_ratio: review_ratio(authored.len(), reviewed.len()),
+    }
+}
+
+/// Count the PRs and issues in each repository, most active first.
+fn repo_totals(
+    authored: &[GithubSearchResponseItem],
+    reviewed: &[GithubSearchResponseItem],
+    issues: &[&GithubSearchResponseItem],
+) -> Vec<RepoSummary> {
+    let mut repos: HashMap<String, RepoSummary> = HashMap::new();
+    let mut count = |item: &GithubSearchResponseItem, add: fn(&mut RepoSummary)| {
+        let Some(name) = repo_full_name(&item.repository_url) else {
+            return;
+        };
+        let repo = repos
+            .entry(name.to_lowercase())
+            .or_insert_with(|| RepoSummary {
+                url: repo_html_url(&item.html_url).unwrap_or_else(|| item.html_url.to_string()),
+                name,
+                authored: 0,
+                reviewed: 0,
+                issues: 0,
+                total: 0,
+            });
+        add(repo);
+        repo.total += 1;
+    };
+
+    authored
+        .iter()
+        .for_each(|pr| count(pr, |r| r.authored += 1));
+    reviewed
+        .iter()
+        .for_each(|pr| count(pr, |r| r.reviewed += 1));
+    issues
+        .iter()
+        .for_each(|issue| count(issue, |r| r.issues += 1));
+
+    let mut repos: Vec<RepoSummary> = repos.into_values().collect();
+    repos.sort_by(|a, b| b.total.cmp(&a.total).then_with(|| a.name.cmp(&b.name)));
+    repos
+}
+
+/// Authored and reviewed PRs per month, from the first month with a PR to the last.
+fn months(
+    authored: &[GithubSearchResponseItem],
+    reviewed: &[GithubSearchResponseItem],
+) -> Vec<MonthSummary> {
+    let month_of = |pr: &GithubSearchResponseItem| pr.created_at.date_naive().with_day(1);
+    let mut counts: BTreeMap<NaiveDate, (usize, usize)> = BTreeMap::new();
+    authored
+        .iter()
+        .filter_map(month_of)
+        .for_each(|month| counts.entry(month).or_default().0 += 1);
+    reviewed
+        .iter()
+        .filter_map(month_of)
+        .for_each(|month| counts.entry(month).or_default().1 += 1);
+
+    let (Some(&first), Some(&last)) = (counts.keys().next(), counts.keys().next_back()) else {
+        return vec![];
+    };
+    std::iter::successors(Some(first), |month| {
+        month.checked_add_months(Months::new(1))
+    })
+    .take_while(|month| *month <= last)
+    .map(|month| {
+        let (authored, reviewed) = counts.get(&month).copied().unwrap_or_default();
+        MonthSummary {
+            month: month.format("%Y-%m").to_string(),
+            authored,
+            reviewed,
+        }
+    })
+    .collect()
+}
+
+/// The most used labels, compared by name regardless of case, counting each item once.
+fn top_labels<'a>(items: impl Iterator<Item = &'a GithubSearchResponseItem>) -> Vec<LabelCount> {
+    let mut labels: HashMap<String, LabelCount> = HashMap::new();
+    for item in items {
+        let mut seen = HashSet::new();
+        for label in &item.labels {
+            let key = label.name.to_lowercase();
+            if !seen.insert(key.clone()) {
+                continue;
+            }
+            labels
+                .entry(key)
+                .or_insert_with(|| LabelCount {
+                    name: label.name.to_string(),
+                    color: label.color.to_string(),
+                    count: 0,
+                })
+                .count += 1;
+        }
+    }
+
+    let mut labels: Vec<LabelCount> = labels.into_values().collect();
+    labels.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name)));
+    labels.truncate(TOP_LABELS);
+    labels
+}
+
+/// Reviews per authored PR, to two decimal places, or `None` without any authored PRs.
+fn review_ratio(authored: usize, reviewed: usize) -> Option<f64> {
+    match authored {
+        0 => None,
+        len => Some((reviewed as f64 / len as f64 * 100.0).round() / 100.0),
+    }
+}
+
+/// The weeks, starting on Monday, with the most activity.
+fn busiest_weeks(activity: &[DateTime<Utc>]) -> Vec<WeekSummary> {
+    let mut weeks: HashMap<NaiveDate, usize> = HashMap::new();
+    for at in activity {
+        *weeks.entry(week_of(at.date_naive())).or_default() += 1;
+    }
+
+    let mut weeks: Vec<(NaiveDate, usize)> = weeks.into_iter().collect();
+    weeks.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    weeks
+        .into_iter()
+        .take(BUSIEST_WEEKS)
+        .map(|(monday, count)| WeekSummary {
+            week_of: monday.to_string(),
+            count,
+        })
+        .collect()
+}
+
+/// When the user reviewed a PR: when they first responded, if known, rather than when the PR
+/// was opened, since that's when the work happened.
+pub fn reviewed_at(pr: &GithubSearchResponseItem) -> DateTime<Utc> {
+    pr.review_activity
+        .as_ref()
+        .and_then(|a| a.first_response_at)
+        .unwrap_or(pr.created_at)
+}
+
+/// The Monday of the week a date falls in.
+pub fn week_of(date: NaiveDate) -> NaiveDate {
+    date - Days::new(date.weekday().num_days_from_monday() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn item(number: u32, labels: &[(&str, &str)]) -> GithubSearchResponseItem {
+        let repo = "https://api.github.com/repos/guardian/frontend";
+        let labels: Vec<_> = labels
+            .iter()
+            .map(|(name, color)| {
+                json!({
+                    "node_id": format!("LA_{}", name),
+                    "url": format!("{}/labels/{}", repo, name),
+                    "name": name,
+                    "color": color
+                })
+            })
+            .collect();
+        serde_json::from_value(json!({
+            "url": format!("{}/issues/{}", repo, number),
+            "repository_url": repo,
+            "labels_url": format!("{}/issues/{}/labels{{/name}}", repo, number),
+            "comments_url": format!("{}/issues/{}/comments", repo, number),
+            "events_url": format!("{}/issues/{}/events", repo, number),
+            "html_url": format!("https://github.com/guardian/frontend/issues/{}", number),
+            "id": number,
+            "node_id": format!("I_{}", number),
+            "number": number,
+            "title": "An issue",
+            "user": {
+                "login": "octo",
+                "id": 1,
+                "node_id": "MDQ6VXNlcjE=",
+                "avatar_url": "https://avatars.githubusercontent.com/u/1?v=4",
+                "gravatar_id": "",
+                "url": "https://api.github.com/users/octo",
+                "html_url": "https://github.com/octo",
+                "followers_url": "https://api.github.com/users/octo/followers",
+                "gists_url": "https://api.github.com/users/octo/gists{/gist_id}",
+                "starred_url": "https://api.github.com/users/octo/starred{/owner}{/repo}",
+                "subscriptions_url": "https://api.github.com/users/octo/subscriptions",
+                "organizations_url": "https://api.github.com/users/octo/orgs",
+                "repos_url": "https://api.github.com/users/octo/repos",
+                "events_url": "https://api.github.com/users/octo/events{/privacy}",
+                "received_events_url": "https://api.github.com/users/octo/received_events",
+                "type": "User",
+                "site_admin": false
+            },
+            "labels": labels,
+            "state": "open",
+            "assignee": null,
+            "comments": 0,
+            "created_at": "2026-04-01T10:00:00Z",
+            "updated_at": "2026-04-01T10:00:00Z",
+            "closed_at": null,
+            "pull_request": null,
+            "body": null,
+            "locked": false,
+            "author_association": "MEMBER"
+        }))
+        .unwrap()
+    }
+
+    fn at(date: &str) -> DateTime<Utc> {
+        format!("{}T12:00:00Z", date).parse().unwrap()
+    }
+
+    #[test]
+    fn top_labels_count_each_item_once_regardless_of_case() {
+        let items = [
+            item(1, &[("bug", "d73a4a"), ("Bug", "ff0000")]),
+            item(2, &[("BUG", "d73a4a"), ("docs", "0075ca")]),
+            item(3, &[("docs", "0075ca")]),
+            item(4, &[("chore", "ededed")]),
+        ];
+
+        let labels: Vec<(String, usize)> = top_labels(items.iter())
+            .into_iter()
+            .map(|label| (label.name, label.count))
+            .collect();
+
+        assert_eq!(
+            labels,
+            [
+                ("bug".to_string(), 2),
+                ("docs".to_string(), 2),
+                ("chore".to_string(), 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn top_labels_are_limited() {
+        let items: Vec<_> = (0..TOP_LABELS as u32 + 2)
+            .map(|n| item(n, &[(&format!("label {:02}", n), "ededed")]))
+            .collect();
+
+        assert_eq!(top_labels(items.iter()).len(), TOP_LABELS);
+    }
+
+    #[test]
+    fn busiest_weeks_start_on_monday_and_break_ties_by_date() {
+        let activity = [
+            // Sunday, in the week of Monday the 6th.
+            at("2026-04-12"),
+            at("2026-04-06"),
+            at("2026-04-20"),
+            at("2026-04-21"),
+            at("2026-04-01"),
+            at("2026-04-13"),
+        ];
+
+        let weeks: Vec<(String, usize)> = busiest_weeks(&activity)
+            .into_iter()
+            .map(|week| (week.week_of, week.count))
+            .collect();
+
+        assert_eq!(
+            weeks,
+            [
+                ("2026-04-06".to_string(), 2),
+                ("2026-04-20".to_string(), 2),
+                ("2026-03-30".to_string(), 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn busiest_weeks_are_empty_without_activity() {
+        assert!(busiest_weeks(&[]).is_empty());
+    }
+
+    #[test]
+    fn review_ratio_needs_authored_prs() {
+        assert_eq!(review_ratio(0, 0), None);
+        assert_eq!(review_ratio(0, 5), None);
+        assert_eq!(review_ratio(3, 2), Some(0.67));
+        assert_eq!(review_ratio(2, 5), Some(2.5));
+    }
+}
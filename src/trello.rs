@@ -1,8 +1,16 @@
+use crate::cache::Cache;
+use crate::cli::{ReportFormat, TrelloDateField};
+use crate::config::get_setting;
+use crate::dates::DateRange;
+use crate::github::escape_markdown;
 use crate::models::*;
-use chrono::DateTime;
+use chrono::{DateTime, Utc};
 use std::collections::HashMap;
 use std::error::Error;
 
+/// The most actions Trello returns in one request.
+const TRELLO_ACTIONS_PAGE_SIZE: usize = 1000;
+
 pub async fn search_trello_user(
     trello_client: &reqwest::Client,
     trello_key: String,
@@ -14,9 +22,12 @@ pub async fn search_trello_user(
             &trello_key, &trello_token
         ))
         .send()
-        .await?
+        .await
+        // The URLs hold the credentials, so leave them out of any error
+        .map_err(|err| err.without_url())?
         .json()
-        .await?;
+        .await
+        .map_err(|err| err.without_url())?;
 
     Ok(trello_user)
 }
@@ -26,8 +37,9 @@ pub async fn search_trello(
     trello_key: String,
     trello_token: String,
     trello_user: &TrelloUser,
-    from: &str,
-    to: &str,
+    date_range: &DateRange,
+    date_field: TrelloDateField,
+    cache: Option<&Cache>,
 ) -> anyhow::Result<HashMap<String, Vec<TrelloCard>>> {
     println!("[self-assessment] 🃏 Collecting your Trello cards...");
     let response: Vec<TrelloBoard> = trello_client
@@ -36,9 +48,12 @@ pub async fn search_trello(
             &trello_key, &trello_token
         ))
         .send()
-        .await?
+        .await
+        // The URLs hold the credentials, so leave them out of any error
+        .map_err(|err| err.without_url())?
         .json()
-        .await?;
+        .await
+        .map_err(|err| err.without_url())?;
 
     let board_ids = response
         .iter()
@@ -47,22 +62,44 @@ pub async fn search_trello(
 
     let mut trello_cards: HashMap<String, Vec<TrelloCard>> = HashMap::new();
 
+    if let Some(cache) = cache {
+        cache.retain_trello_boards(&board_ids.keys().collect::<Vec<&String>>())?;
+    }
+
     for (board_id, board_name) in board_ids {
         let all_cards_in_board: Vec<TrelloCard> = trello_client
             .get(format!(
-                r#"https://api.trello.com/1/boards/{}/cards/all?key={}&token={}&fields=url,idMembers,name,desc,dateLastActivity,labels"#,
+                r#"https://api.trello.com/1/boards/{}/cards/all?key={}&token={}&fields=url,idMembers,name,desc,dateLastActivity,due,labels"#,
                 board_id, &trello_key, &trello_token
             ))
             .send()
-            .await?
+            .await
+            .map_err(|err| err.without_url())?
             .json()
-            .await?;
+            .await
+            .map_err(|err| err.without_url())?;
+
+        // Cached cards need their done dates too, so offline reports can match on them
+        let done_dates = match (date_field, cache) {
+            (TrelloDateField::Done, _) | (_, Some(_)) => {
+                search_done_dates(trello_client, &trello_key, &trello_token, &board_id).await?
+            }
+            _ => HashMap::new(),
+        };
 
         // Only collect trello cards you're assigned to
-        let my_cards_only: Vec<TrelloCard> = all_cards_in_board
+        let my_cards: Vec<TrelloCard> = all_cards_in_board
             .into_iter()
             .filter(|card| card.id_members.contains(&trello_user.id))
-            .filter(|card| trello_cards_date_range(card, from, to))
+            .collect();
+
+        if let Some(cache) = cache {
+            cache.store_trello_board(&board_id, &board_name, &my_cards, &done_dates)?;
+        }
+
+        let my_cards_only: Vec<TrelloCard> = my_cards
+            .into_iter()
+            .filter(|card| trello_card_in_range(card, date_range, date_field, &done_dates))
             .collect();
 
         if !my_cards_only.is_empty() {
@@ -73,24 +110,164 @@ pub async fn search_trello(
     Ok(trello_cards)
 }
 
-pub fn trello_cards_date_range(card: &TrelloCard, from: &str, to: &str) -> bool {
-    let from = match from {
-        "*" => true,
-        _ => {
-            card.date_last_activity
-                >= DateTime::parse_from_rfc3339(&format!("{}T00:00:00.00Z", from)).unwrap()
+/// Load the Trello cards assigned to the user from the cache, without going online.
+pub fn cached_trello_cards(
+    cache: &Cache,
+    date_range: &DateRange,
+    date_field: TrelloDateField,
+) -> anyhow::Result<HashMap<String, Vec<TrelloCard>>> {
+    let cached_cards = cache.trello_cards()?;
+    let done_dates = cached_cards
+        .iter()
+        .filter_map(|c| c.done_at.map(|d| (c.card.id.clone(), d)))
+        .collect::<HashMap<String, DateTime<Utc>>>();
+
+    let mut trello_cards: HashMap<String, Vec<TrelloCard>> = HashMap::new();
+    for cached in cached_cards {
+        if trello_card_in_range(&cached.card, date_range, date_field, &done_dates) {
+            trello_cards
+                .entry(cached.board_name)
+                .or_default()
+                .push(cached.card);
         }
-    };
+    }
 
-    let to = match to {
-        "*" => true,
-        _ => {
-            card.date_last_activity
-                <= DateTime::parse_from_rfc3339(&format!("{}T00:00:00.00Z", to)).unwrap()
+    Ok(trello_cards)
+}
+
+/// Revoke a server token, so it can't be used any more.
+pub async fn revoke_trello_token(
+    trello_client: &reqwest::Client,
+    trello_key: &str,
+    trello_token: &str,
+) -> anyhow::Result<()> {
+    let response = trello_client
+        .delete(format!(
+            "https://api.trello.com/1/tokens/{}?key={}&token={}",
+            trello_token, trello_key, trello_token
+        ))
+        .send()
+        .await
+        // The URL holds the credentials, so leave it out of the error
+        .map_err(|err| err.without_url())?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let message = response.text().await.unwrap_or_default();
+        anyhow::bail!(
+            "Trello returned {} when revoking the token: {}",
+            status,
+            message.trim()
+        );
+    }
+    Ok(())
+}
+
+/// Find when each card on a board was last moved into, or created in, a "Done" list.
+async fn search_done_dates(
+    trello_client: &reqwest::Client,
+    trello_key: &str,
+    trello_token: &str,
+    board_id: &str,
+) -> anyhow::Result<HashMap<String, DateTime<Utc>>> {
+    let mut done_dates: HashMap<String, DateTime<Utc>> = HashMap::new();
+    let mut before: Option<String> = None;
+    let done_lists = get_setting("TRELLO_DONE_LISTS").map(|lists| parse_done_lists(&lists));
+
+    // Actions come back newest first, a page at a time
+    loop {
+        let actions: Vec<TrelloAction> = trello_client
+            .get(format!(
+                "https://api.trello.com/1/boards/{}/actions?key={}&token={}&filter=updateCard:idList,createCard&limit={}{}",
+                board_id,
+                trello_key,
+                trello_token,
+                TRELLO_ACTIONS_PAGE_SIZE,
+                before.as_ref().map(|b| format!("&before={}", b)).unwrap_or_default()
+            ))
+            .send()
+            .await
+            .map_err(|err| err.without_url())?
+            .json()
+            .await
+            .map_err(|err| err.without_url())?;
+
+        for action in &actions {
+            let list = action
+                .data
+                .list_after
+                .as_ref()
+                .or(action.data.list.as_ref());
+            if let (Some(card), Some(list)) = (&action.data.card, list) {
+                if is_done_list(&list.name, done_lists.as_deref()) {
+                    done_dates.entry(card.id.clone()).or_insert(action.date);
+                }
+            }
         }
-    };
 
-    from && to
+        match actions.last() {
+            Some(last) if actions.len() == TRELLO_ACTIONS_PAGE_SIZE => {
+                before = Some(last.id.clone())
+            }
+            _ => break,
+        }
+    }
+
+    Ok(done_dates)
+}
+
+/// The lowercased list names of a comma-separated `TRELLO_DONE_LISTS` setting.
+fn parse_done_lists(lists: &str) -> Vec<String> {
+    lists
+        .split(',')
+        .map(|list| list.trim().to_lowercase())
+        .filter(|list| !list.is_empty())
+        .collect()
+}
+
+/// Whether cards in a list are done: lists named in the `TRELLO_DONE_LISTS` setting, regardless
+/// of case, or without it any list with the word "done" in its name, e.g. "Done this sprint"
+/// but not "Undone".
+fn is_done_list(name: &str, done_lists: Option<&[String]>) -> bool {
+    let name = name.to_lowercase();
+    match done_lists {
+        Some(done_lists) => done_lists.iter().any(|list| *list == name.trim()),
+        None => name
+            .split(|c: char| !c.is_alphanumeric())
+            .any(|word| word == "done"),
+    }
+}
+
+/// The date a card is filtered on.
+/// Cards without that date, e.g. without a due date, are left out of the report.
+pub fn trello_card_date(
+    card: &TrelloCard,
+    date_field: TrelloDateField,
+    done_dates: &HashMap<String, DateTime<Utc>>,
+) -> Option<DateTime<Utc>> {
+    match date_field {
+        // The first 8 hex digits of a Trello id are the Unix timestamp it was created at
+        TrelloDateField::Created => card
+            .id
+            .get(..8)
+            .and_then(|ts| i64::from_str_radix(ts, 16).ok())
+            .and_then(|ts| DateTime::from_timestamp(ts, 0)),
+        TrelloDateField::Done => done_dates.get(&card.id).copied(),
+        TrelloDateField::Due => card.due,
+        TrelloDateField::Activity => Some(card.date_last_activity),
+    }
+}
+
+fn trello_card_in_range(
+    card: &TrelloCard,
+    date_range: &DateRange,
+    date_field: TrelloDateField,
+    done_dates: &HashMap<String, DateTime<Utc>>,
+) -> bool {
+    match trello_card_date(card, date_field, done_dates) {
+        Some(date) => date_range.contains(date.date_naive()),
+        None => false,
+    }
 }
 
 // Returns a tuple containing the number of boards and the number of total cards across all boards
@@ -103,7 +280,26 @@ pub fn trello_board_and_cards_len(boards_with_cards: &[BoardAndCards]) -> (usize
     (board_size, total_cards)
 }
 
-fn template_card_from_unformatted_card(card: &TrelloCard) -> TemplateTrelloCard {
+fn template_card_from_unformatted_card(
+    card: &TrelloCard,
+    format: ReportFormat,
+) -> TemplateTrelloCard {
+    if format == ReportFormat::Markdown {
+        return TemplateTrelloCard {
+            name: escape_markdown(&card.name),
+            url: card.url.to_string(),
+            labels: card
+                .labels
+                .iter()
+                .map(|l| match l.name.as_str() {
+                    "" => format!("`{}`", l.color.as_deref().unwrap_or("no colour")),
+                    name => format!("`{}`", name.replace('`', "'")),
+                })
+                .collect::<Vec<String>>()
+                .join(" "),
+        };
+    }
+
     TemplateTrelloCard {
         name: card.name.to_string(),
         url: card.url.to_string(),
@@ -136,14 +332,17 @@ fn template_card_from_unformatted_card(card: &TrelloCard) -> TemplateTrelloCard
         }
 }
 
-pub fn format_trello_cards(cards: &HashMap<String, Vec<TrelloCard>>) -> Vec<BoardAndCards> {
+pub fn format_trello_cards(
+    cards: &HashMap<String, Vec<TrelloCard>>,
+    format: ReportFormat,
+) -> Vec<BoardAndCards> {
     let mut formatted_cards: HashMap<String, Vec<TemplateTrelloCard>> = HashMap::new();
     for (key, value) in cards.iter() {
         formatted_cards.insert(
             key.to_string(),
             value
                 .iter()
-                .map(template_card_from_unformatted_card)
+                .map(|card| template_card_from_unformatted_card(card, format))
                 .collect::<Vec<TemplateTrelloCard>>(),
         );
     }
@@ -152,3 +351,95 @@ pub fn format_trello_cards(cards: &HashMap<String, Vec<TrelloCard>>) -> Vec<Boar
         .map(|(board, cards)| BoardAndCards { board, cards })
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trello_card(id: &str) -> TrelloCard {
+        TrelloCard {
+            id: id.to_string(),
+            name: "Write the report".to_string(),
+            desc: String::new(),
+            id_members: vec![],
+            url: format!("https://trello.com/c/{}", id),
+            date_last_activity: "2024-03-01T09:00:00Z".parse().unwrap(),
+            due: Some("2024-02-01T17:00:00Z".parse().unwrap()),
+            labels: vec![],
+        }
+    }
+
+    #[test]
+    fn done_lists_have_the_word_done() {
+        for name in [
+            "Done",
+            "done",
+            "Done this sprint",
+            "Sprint 4 - DONE",
+            "Done!",
+        ] {
+            assert!(is_done_list(name, None), "{}", name);
+        }
+        for name in ["Undone", "Not done yet?", "Doing", "Abandoned"] {
+            assert_eq!(
+                is_done_list(name, None),
+                name == "Not done yet?",
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn done_lists_setting_replaces_the_word_done() {
+        let done_lists = parse_done_lists(" Shipped, RELEASED ,,");
+        assert_eq!(done_lists, ["shipped", "released"]);
+
+        assert!(is_done_list("Shipped", Some(&done_lists)));
+        assert!(is_done_list(" released ", Some(&done_lists)));
+        assert!(!is_done_list("Done", Some(&done_lists)));
+        assert!(!is_done_list("Shipped soon", Some(&done_lists)));
+    }
+
+    #[test]
+    fn card_created_date_comes_from_its_id() {
+        let card = trello_card("65a1b2c3d4e5f60718293a4b");
+
+        assert_eq!(
+            trello_card_date(&card, TrelloDateField::Created, &HashMap::new()),
+            Some("2024-01-12T21:44:35Z".parse().unwrap())
+        );
+        assert_eq!(
+            trello_card_date(
+                &trello_card("not an id"),
+                TrelloDateField::Created,
+                &HashMap::new()
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn card_dates_for_each_field() {
+        let card = trello_card("65a1b2c3d4e5f60718293a4b");
+        let done_at: DateTime<Utc> = "2024-02-20T12:00:00Z".parse().unwrap();
+        let done_dates = HashMap::from([(card.id.clone(), done_at)]);
+
+        assert_eq!(
+            trello_card_date(&card, TrelloDateField::Done, &done_dates),
+            Some(done_at)
+        );
+        assert_eq!(
+            trello_card_date(&card, TrelloDateField::Done, &HashMap::new()),
+            None
+        );
+        assert_eq!(
+            trello_card_date(&card, TrelloDateField::Due, &done_dates),
+            card.due
+        );
+        assert_eq!(
+            trello_card_date(&card, TrelloDateField::Activity, &done_dates),
+            Some(card.date_last_activity)
+        );
+    }
+}